@@ -9,12 +9,15 @@ extern crate cortex_m_semihosting as sh;
 extern crate panic_semihosting;
 extern crate stm32l1xx_hal as hal;
 
+use cortex_m::singleton;
 use hal::prelude::*;
 use hal::rcc::Config;
 use hal::stm32;
 use rt::entry;
 use sh::hprintln;
 
+static SRC: [u32; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
 #[entry]
 fn main() -> ! {
     let dp = stm32::Peripherals::take().unwrap();
@@ -24,5 +27,13 @@ fn main() -> ! {
     let dma2 = dp.DMA2.dma(&mut rcc);
 
     hprintln!("DMA1: {:?}, DMA2: {:?}", dma1, dma2).unwrap();
+
+    let dst = singleton!(: [u32; 8] = [0; 8]).unwrap();
+    let (_, (_, dst)) = dma1.1.copy(&SRC, dst).unwrap().wait().unwrap();
+    hprintln!("copy: {:?}", dst).unwrap();
+
+    let (_, dst) = dma2.1.fill(0xAA55_AA55, dst).unwrap().wait().unwrap();
+    hprintln!("fill: {:?}", dst).unwrap();
+
     loop {}
 }
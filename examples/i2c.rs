@@ -26,7 +26,7 @@ fn main() -> ! {
     let scl = gpiob.pb10.into_open_drain_output();
     let sda = gpiob.pb11.into_open_drain_output();
 
-    let mut i2c = dp.I2C2.i2c((scl, sda), 10.khz(), &mut rcc);
+    let mut i2c = dp.I2C2.i2c((scl, sda), 10.khz(), &mut rcc).unwrap();
 
     let mut buf: [u8; 1] = [0; 1];
 
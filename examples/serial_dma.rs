@@ -0,0 +1,58 @@
+#![deny(warnings)]
+#![deny(unsafe_code)]
+#![no_main]
+#![no_std]
+
+extern crate cortex_m;
+extern crate cortex_m_rt as rt;
+extern crate panic_semihosting;
+extern crate stm32l1xx_hal as hal;
+
+use cortex_m::singleton;
+use hal::prelude::*;
+use hal::rcc::Config;
+use hal::serial;
+use hal::serial::SerialExt;
+use hal::stm32;
+use rt::entry;
+
+#[entry]
+fn main() -> ! {
+    let dp = stm32::Peripherals::take().unwrap();
+
+    let mut rcc = dp.RCC.freeze(Config::hsi());
+    let dma1 = dp.DMA1.dma(&mut rcc);
+
+    let gpioa = dp.GPIOA.split();
+    let tx = gpioa.pa9;
+    let rx = gpioa.pa10;
+
+    let serial = dp
+        .USART1
+        .usart((tx, rx), serial::Config::default(), &mut rcc)
+        .unwrap();
+
+    let (tx, mut rx) = serial.split();
+
+    let (mut tx, mut tx_channel, _) = tx
+        .write_all_dma(dma1.4, b"Type 8 characters\r\n")
+        .unwrap()
+        .wait()
+        .unwrap();
+
+    let mut rx_channel = dma1.5;
+    let mut buf = singleton!(: [u8; 8] = [0; 8]).unwrap();
+
+    loop {
+        let (r, c, b) = rx.read_exact_dma(rx_channel, buf).unwrap().wait().unwrap();
+
+        // echo the received block back
+        let (t, c2, b) = tx.write_all_dma(tx_channel, b).unwrap().wait().unwrap();
+
+        rx = r;
+        rx_channel = c;
+        tx = t;
+        tx_channel = c2;
+        buf = b;
+    }
+}
@@ -1,18 +1,636 @@
 //! Direct Memory Access Engine
+use core::sync::atomic::{self, Ordering};
+
+use crate::dac;
 use crate::rcc::Rcc;
+use crate::stm32::{
+    ADC, I2C1, I2C2, SPI1, SPI2, SPI3, TIM2, TIM3, TIM4, TIM5, TIM6, TIM7, USART1, USART2, USART3,
+};
+#[cfg(any(feature = "stm32l151", feature = "stm32l152", feature = "stm32l162"))]
+use crate::stm32::{UART4, UART5};
 
 #[derive(Debug)]
 pub enum Error {
     Overrun,
     BufferError,
+    /// Bus error while accessing the peripheral or memory address
+    TransferError,
 }
 
 #[derive(Debug)]
 pub enum Event {
     HalfTransfer,
     TransferComplete,
+    TransferError,
+}
+
+/// Channel priority level
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Priority {
+    Low = 0b00,
+    Medium = 0b01,
+    High = 0b10,
+    VeryHigh = 0b11,
+}
+
+/// Data transfer direction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    /// Read from the peripheral register, write to memory
+    PeripheralToMemory,
+    /// Read from memory, write to the peripheral register
+    MemoryToPeripheral,
+    /// Read from memory, write to memory at the peripheral address
+    MemoryToMemory,
+}
+
+/// Size of a single data item (PSIZE/MSIZE encoding)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WordSize {
+    Bits8 = 0b00,
+    Bits16 = 0b01,
+    Bits32 = 0b10,
+}
+
+/// Data item that can be moved by the DMA engine
+///
+/// # Safety
+///
+/// `SIZE` must match the size of the implementing type.
+pub unsafe trait Word: Copy {
+    const SIZE: WordSize;
+}
+
+unsafe impl Word for u8 {
+    const SIZE: WordSize = WordSize::Bits8;
+}
+
+unsafe impl Word for u16 {
+    const SIZE: WordSize = WordSize::Bits16;
+}
+
+unsafe impl Word for u32 {
+    const SIZE: WordSize = WordSize::Bits32;
+}
+
+/// Buffer the DMA engine can read from
+///
+/// # Safety
+///
+/// The returned pointer and length must describe memory that stays valid and
+/// unmoved for as long as the buffer is owned by a transfer, even if the
+/// buffer value itself is moved.
+pub unsafe trait ReadBuffer {
+    type Word;
+
+    /// Returns the start address and the number of words of the buffer
+    ///
+    /// # Safety
+    ///
+    /// The memory must not be written through the buffer while the DMA engine
+    /// reads it.
+    unsafe fn read_buffer(&self) -> (*const Self::Word, usize);
+}
+
+/// Buffer the DMA engine can write to
+///
+/// # Safety
+///
+/// Same requirements as for [`ReadBuffer`].
+pub unsafe trait WriteBuffer {
+    type Word;
+
+    /// Returns the start address and the number of words of the buffer
+    ///
+    /// # Safety
+    ///
+    /// The memory must not be accessed through the buffer while the DMA
+    /// engine writes it.
+    unsafe fn write_buffer(&mut self) -> (*mut Self::Word, usize);
+}
+
+unsafe impl<W: Word> ReadBuffer for &'static [W] {
+    type Word = W;
+
+    unsafe fn read_buffer(&self) -> (*const W, usize) {
+        (self.as_ptr(), self.len())
+    }
+}
+
+unsafe impl<W: Word> ReadBuffer for &'static mut [W] {
+    type Word = W;
+
+    unsafe fn read_buffer(&self) -> (*const W, usize) {
+        (self.as_ptr(), self.len())
+    }
+}
+
+unsafe impl<W: Word, const N: usize> ReadBuffer for &'static [W; N] {
+    type Word = W;
+
+    unsafe fn read_buffer(&self) -> (*const W, usize) {
+        (self.as_ptr(), N)
+    }
+}
+
+unsafe impl<W: Word, const N: usize> ReadBuffer for &'static mut [W; N] {
+    type Word = W;
+
+    unsafe fn read_buffer(&self) -> (*const W, usize) {
+        (self.as_ptr(), N)
+    }
+}
+
+unsafe impl<W: Word> WriteBuffer for &'static mut [W] {
+    type Word = W;
+
+    unsafe fn write_buffer(&mut self) -> (*mut W, usize) {
+        (self.as_mut_ptr(), self.len())
+    }
+}
+
+unsafe impl<W: Word, const N: usize> WriteBuffer for &'static mut [W; N] {
+    type Word = W;
+
+    unsafe fn write_buffer(&mut self) -> (*mut W, usize) {
+        (self.as_mut_ptr(), N)
+    }
+}
+
+/// Low level access to a DMA channel
+pub trait Channel: Sized {
+    /// Sets the peripheral register address and whether it is incremented
+    /// after every data item
+    ///
+    /// # Safety
+    ///
+    /// The DMA engine will access `address` once the channel is started.
+    unsafe fn set_peripheral_address(&mut self, address: u32, inc: bool);
+
+    /// Sets the memory address and whether it is incremented after every
+    /// data item
+    ///
+    /// # Safety
+    ///
+    /// The DMA engine will access `address` once the channel is started.
+    unsafe fn set_memory_address(&mut self, address: u32, inc: bool);
+
+    /// Sets the number of data items to transfer
+    ///
+    /// # Safety
+    ///
+    /// The DMA engine will access that many items at the configured
+    /// addresses once the channel is started.
+    unsafe fn set_transfer_length(&mut self, len: u16);
+
+    /// Returns the number of data items left to transfer
+    fn remaining(&self) -> u16;
+
+    /// Sets the priority used to arbitrate between requests on the same controller
+    fn set_priority(&mut self, priority: Priority);
+
+    /// Configures direction, data item sizes and circular mode
+    ///
+    /// # Safety
+    ///
+    /// The direction and sizes decide which memory the DMA engine writes
+    /// once the channel is started.
+    unsafe fn configure(
+        &mut self,
+        dir: Direction,
+        psize: WordSize,
+        msize: WordSize,
+        circular: bool,
+    );
+
+    /// Clears the channel flags and enables the channel
+    ///
+    /// # Safety
+    ///
+    /// The configured addresses must stay valid for the DMA engine until the
+    /// channel is stopped, and nothing else may use the memory in the
+    /// meantime.
+    unsafe fn start(&mut self);
+
+    /// Disables the channel and clears its flags
+    fn stop(&mut self);
+
+    /// Returns `true` if the flag of the given event is set
+    fn is_event(&self, event: Event) -> bool;
+
+    /// Clears the flag of the given event
+    fn clear_event(&mut self, event: Event);
+
+    /// Copies the contents of `src` into the beginning of `dst`
+    ///
+    /// On failure the channel and the buffers are handed back with the error.
+    #[allow(clippy::type_complexity)]
+    fn copy<SRC, DST>(
+        mut self,
+        src: SRC,
+        mut dst: DST,
+    ) -> Result<Transfer<Self, (SRC, DST)>, (Error, Self, (SRC, DST))>
+    where
+        SRC: ReadBuffer,
+        SRC::Word: Word,
+        DST: WriteBuffer<Word = SRC::Word>,
+    {
+        let (src_ptr, src_len) = unsafe { src.read_buffer() };
+        let (dst_ptr, dst_len) = unsafe { dst.write_buffer() };
+        if dst_len < src_len {
+            return Err((Error::BufferError, self, (src, dst)));
+        }
+
+        let res = unsafe {
+            setup_mem2mem(
+                &mut self,
+                src_ptr as u32,
+                true,
+                dst_ptr as u32,
+                src_len,
+                SRC::Word::SIZE,
+            )
+        };
+        if let Err(error) = res {
+            return Err((error, self, (src, dst)));
+        }
+
+        Ok(Transfer::start(self, (src, dst)))
+    }
+
+    /// Sets every item of `dst` to `value`
+    ///
+    /// On failure the channel and the buffer are handed back with the error.
+    fn fill<DST>(
+        mut self,
+        value: DST::Word,
+        mut dst: DST,
+    ) -> Result<Transfer<Self, DST>, (Error, Self, DST)>
+    where
+        DST: WriteBuffer,
+        DST::Word: Word,
+    {
+        let (ptr, len) = unsafe { dst.write_buffer() };
+        let res = unsafe {
+            setup_mem2mem(
+                &mut self,
+                ptr as u32,
+                false,
+                ptr as u32,
+                len,
+                DST::Word::SIZE,
+            )
+        };
+        if let Err(error) = res {
+            return Err((error, self, dst));
+        }
+
+        // the first item is the source the engine replicates over the whole buffer
+        unsafe { ptr.write_volatile(value) };
+
+        Ok(Transfer::start(self, dst))
+    }
+}
+
+/// One-shot DMA transfer
+///
+/// Owns the channel and the buffer until the transfer is finished.
+#[derive(Debug)]
+pub struct Transfer<CHANNEL, BUFFER> {
+    channel: CHANNEL,
+    buffer: BUFFER,
+}
+
+impl<CHANNEL, BUFFER> Transfer<CHANNEL, BUFFER>
+where
+    CHANNEL: Channel,
+{
+    /// Starts moving data from the peripheral register at `paddr` into `buffer`
+    ///
+    /// On failure the channel and the buffer are handed back with the error.
+    ///
+    /// # Safety
+    ///
+    /// `paddr` must be the address of a peripheral data register that is
+    /// `BUFFER::Word` wide and is configured to issue requests on `channel`.
+    pub unsafe fn peripheral_to_memory(
+        mut channel: CHANNEL,
+        paddr: u32,
+        mut buffer: BUFFER,
+    ) -> Result<Self, (Error, CHANNEL, BUFFER)>
+    where
+        BUFFER: WriteBuffer,
+        BUFFER::Word: Word,
+    {
+        let (ptr, len) = buffer.write_buffer();
+        if let Err(error) = setup(
+            &mut channel,
+            Direction::PeripheralToMemory,
+            paddr,
+            ptr as u32,
+            len,
+            BUFFER::Word::SIZE,
+            false,
+        ) {
+            return Err((error, channel, buffer));
+        }
+
+        Ok(Self::start(channel, buffer))
+    }
+
+    /// Starts moving data from `buffer` into the peripheral register at `paddr`
+    ///
+    /// On failure the channel and the buffer are handed back with the error.
+    ///
+    /// # Safety
+    ///
+    /// `paddr` must be the address of a peripheral data register that is
+    /// `BUFFER::Word` wide and is configured to issue requests on `channel`.
+    pub unsafe fn memory_to_peripheral(
+        mut channel: CHANNEL,
+        buffer: BUFFER,
+        paddr: u32,
+    ) -> Result<Self, (Error, CHANNEL, BUFFER)>
+    where
+        BUFFER: ReadBuffer,
+        BUFFER::Word: Word,
+    {
+        let (ptr, len) = buffer.read_buffer();
+        if let Err(error) = setup(
+            &mut channel,
+            Direction::MemoryToPeripheral,
+            paddr,
+            ptr as u32,
+            len,
+            BUFFER::Word::SIZE,
+            false,
+        ) {
+            return Err((error, channel, buffer));
+        }
+
+        Ok(Self::start(channel, buffer))
+    }
+
+    fn start(mut channel: CHANNEL, buffer: BUFFER) -> Self {
+        // the buffer must be completely written before the DMA engine reads it
+        atomic::compiler_fence(Ordering::Release);
+        // NOTE(unsafe) the transfer owns the buffer until the channel is stopped
+        unsafe { channel.start() };
+        Transfer { channel, buffer }
+    }
+
+    /// Returns `true` if the transfer has finished, either completely or
+    /// because of a transfer error
+    pub fn is_done(&self) -> bool {
+        self.channel.is_event(Event::TransferComplete)
+            || self.channel.is_event(Event::TransferError)
+    }
+
+    /// Returns the number of data items left to transfer
+    pub fn remaining(&self) -> u16 {
+        self.channel.remaining()
+    }
+
+    /// Blocks until the transfer is finished and releases the channel and the buffer
+    ///
+    /// After a transfer error the channel and the buffer are handed back with
+    /// the error.
+    pub fn wait(self) -> Result<(CHANNEL, BUFFER), (Error, CHANNEL, BUFFER)> {
+        while !self.is_done() {}
+
+        let error = self.channel.is_event(Event::TransferError);
+        let (channel, buffer) = self.abort();
+        if error {
+            Err((Error::TransferError, channel, buffer))
+        } else {
+            Ok((channel, buffer))
+        }
+    }
+
+    /// Stops the transfer and releases the channel and the buffer
+    pub fn abort(mut self) -> (CHANNEL, BUFFER) {
+        self.channel.stop();
+        // the DMA engine is done with the buffer before anybody reads it
+        atomic::compiler_fence(Ordering::Acquire);
+        (self.channel, self.buffer)
+    }
 }
 
+/// Buffer half of a circular transfer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Half {
+    First,
+    Second,
+}
+
+/// Circular DMA transfer over a double buffer
+///
+/// The DMA engine cycles through both halves of the buffer endlessly. Once it
+/// moves on from a half, that half is handed to the application by
+/// [`CircBuffer::peek`] while the engine works on the other one.
+#[derive(Debug)]
+pub struct CircBuffer<CHANNEL, W: 'static, const N: usize> {
+    channel: CHANNEL,
+    buffer: &'static mut [[W; N]; 2],
+}
+
+impl<CHANNEL, W, const N: usize> CircBuffer<CHANNEL, W, N>
+where
+    CHANNEL: Channel,
+    W: Word,
+{
+    /// Starts continuously filling `buffer` from the peripheral register at `paddr`
+    ///
+    /// A half becomes readable once the DMA engine has filled it.
+    ///
+    /// On failure the channel and the buffer are handed back with the error.
+    ///
+    /// # Safety
+    ///
+    /// `paddr` must be the address of a peripheral data register that is `W`
+    /// wide and is configured to issue requests on `channel`.
+    pub unsafe fn peripheral_to_memory(
+        mut channel: CHANNEL,
+        paddr: u32,
+        buffer: &'static mut [[W; N]; 2],
+    ) -> Result<Self, (Error, CHANNEL, &'static mut [[W; N]; 2])> {
+        if let Err(error) = setup(
+            &mut channel,
+            Direction::PeripheralToMemory,
+            paddr,
+            buffer.as_ptr() as u32,
+            2 * N,
+            W::SIZE,
+            true,
+        ) {
+            return Err((error, channel, buffer));
+        }
+
+        Ok(Self::start(channel, buffer))
+    }
+
+    /// Starts continuously sending `buffer` to the peripheral register at `paddr`
+    ///
+    /// A half becomes writable once the DMA engine has sent it, so it can be
+    /// refilled while the other half is sent.
+    ///
+    /// On failure the channel and the buffer are handed back with the error.
+    ///
+    /// # Safety
+    ///
+    /// `paddr` must be the address of a peripheral data register that is `W`
+    /// wide and is configured to issue requests on `channel`.
+    pub unsafe fn memory_to_peripheral(
+        mut channel: CHANNEL,
+        buffer: &'static mut [[W; N]; 2],
+        paddr: u32,
+    ) -> Result<Self, (Error, CHANNEL, &'static mut [[W; N]; 2])> {
+        if let Err(error) = setup(
+            &mut channel,
+            Direction::MemoryToPeripheral,
+            paddr,
+            buffer.as_ptr() as u32,
+            2 * N,
+            W::SIZE,
+            true,
+        ) {
+            return Err((error, channel, buffer));
+        }
+
+        Ok(Self::start(channel, buffer))
+    }
+
+    fn start(mut channel: CHANNEL, buffer: &'static mut [[W; N]; 2]) -> Self {
+        atomic::compiler_fence(Ordering::Release);
+        // NOTE(unsafe) the transfer owns the buffer until the channel is stopped
+        unsafe { channel.start() };
+        CircBuffer { channel, buffer }
+    }
+
+    /// Returns the half the DMA engine has finished with
+    ///
+    /// Fails with `Error::Overrun` if the engine finished both halves since the
+    /// last call to `peek`, i.e. the application fell behind, and with
+    /// `Error::TransferError` if the engine stopped because of a bus error.
+    pub fn readable_half(&self) -> nb::Result<Half, Error> {
+        if self.channel.is_event(Event::TransferError) {
+            return Err(nb::Error::Other(Error::TransferError));
+        }
+
+        let first = self.channel.is_event(Event::HalfTransfer);
+        let second = self.channel.is_event(Event::TransferComplete);
+
+        match (first, second) {
+            (true, true) => Err(nb::Error::Other(Error::Overrun)),
+            (true, false) => Ok(Half::First),
+            (false, true) => Ok(Half::Second),
+            (false, false) => Err(nb::Error::WouldBlock),
+        }
+    }
+
+    /// Gives `f` access to the half the DMA engine has finished with
+    ///
+    /// Returns `WouldBlock` if no half is ready yet. Fails with
+    /// `Error::Overrun` if the engine got back to the half while `f` was
+    /// still working on it, or if the application fell behind by a whole
+    /// buffer; in the latter case both halves are dropped.
+    pub fn peek<R, F>(&mut self, f: F) -> nb::Result<R, Error>
+    where
+        F: FnOnce(&mut [W; N], Half) -> R,
+    {
+        let half = match self.readable_half() {
+            Err(nb::Error::Other(Error::Overrun)) => {
+                self.channel.clear_event(Event::HalfTransfer);
+                self.channel.clear_event(Event::TransferComplete);
+                return Err(nb::Error::Other(Error::Overrun));
+            }
+            res => res?,
+        };
+
+        match half {
+            Half::First => self.channel.clear_event(Event::HalfTransfer),
+            Half::Second => self.channel.clear_event(Event::TransferComplete),
+        }
+
+        atomic::compiler_fence(Ordering::Acquire);
+        let ret = f(&mut self.buffer[half as usize], half);
+        atomic::compiler_fence(Ordering::Release);
+
+        // the DMA engine moved on to the half we just handled
+        let overrun = match half {
+            Half::First => self.channel.is_event(Event::TransferComplete),
+            Half::Second => self.channel.is_event(Event::HalfTransfer),
+        };
+
+        if overrun {
+            Err(nb::Error::Other(Error::Overrun))
+        } else {
+            Ok(ret)
+        }
+    }
+
+    /// Stops the transfer and releases the channel and the buffer
+    pub fn stop(mut self) -> (CHANNEL, &'static mut [[W; N]; 2]) {
+        self.channel.stop();
+        atomic::compiler_fence(Ordering::Acquire);
+        (self.channel, self.buffer)
+    }
+}
+
+unsafe fn setup_mem2mem<CHANNEL>(
+    channel: &mut CHANNEL,
+    src: u32,
+    src_inc: bool,
+    dst: u32,
+    len: usize,
+    size: WordSize,
+) -> Result<(), Error>
+where
+    CHANNEL: Channel,
+{
+    if len == 0 || len > usize::from(u16::MAX) {
+        return Err(Error::BufferError);
+    }
+
+    channel.set_memory_address(src, src_inc);
+    channel.set_peripheral_address(dst, true);
+    channel.set_transfer_length(len as u16);
+    channel.configure(Direction::MemoryToMemory, size, size, false);
+    Ok(())
+}
+
+pub(crate) unsafe fn setup<CHANNEL>(
+    channel: &mut CHANNEL,
+    dir: Direction,
+    paddr: u32,
+    maddr: u32,
+    len: usize,
+    size: WordSize,
+    circular: bool,
+) -> Result<(), Error>
+where
+    CHANNEL: Channel,
+{
+    if len == 0 || len > usize::from(u16::MAX) {
+        return Err(Error::BufferError);
+    }
+
+    channel.set_peripheral_address(paddr, false);
+    channel.set_memory_address(maddr, true);
+    channel.set_transfer_length(len as u16);
+    channel.configure(dir, size, size, circular);
+    Ok(())
+}
+
+/// Channel the transmit (memory-to-peripheral) request of `PERIPH` is wired to
+pub trait TxChannel<PERIPH>: Channel {}
+
+/// Channel the receive (peripheral-to-memory) request of `PERIPH` is wired to
+pub trait RxChannel<PERIPH>: Channel {}
+
+/// Channel the update request of the timer `TIM` is wired to
+pub trait UpdateChannel<TIM>: Channel {}
+
 pub trait DmaExt {
     type Channels;
 
@@ -32,15 +650,17 @@ macro_rules! dma {
             $CMARX:ident,
             $htifX:ident,
             $tcifX:ident,
+            $teifX:ident,
             $chtifX:ident,
             $ctcifX:ident,
+            $cteifX:ident,
             $cgifX:ident
         ),)+
     }),)+) => {
         $(
             pub mod $dmaX {
                 use crate::stm32::{$DMAX};
-                use crate::dma::{DmaExt, Event};
+                use crate::dma::{Channel, Direction, DmaExt, Event, Priority, WordSize};
                 use crate::rcc::Rcc;
 
                 #[derive(Debug)]
@@ -59,6 +679,9 @@ macro_rules! dma {
                                 },
                                 Event::TransferComplete => unsafe {
                                     (*$DMAX::ptr()).$ccrX.modify(|_, w| w.tcie().set_bit())
+                                },
+                                Event::TransferError => unsafe {
+                                    (*$DMAX::ptr()).$ccrX.modify(|_, w| w.teie().set_bit())
                                 }
                             }
                         }
@@ -70,10 +693,98 @@ macro_rules! dma {
                                 },
                                 Event::TransferComplete => unsafe {
                                     (*$DMAX::ptr()).$ccrX.modify(|_, w| w.tcie().clear_bit())
+                                },
+                                Event::TransferError => unsafe {
+                                    (*$DMAX::ptr()).$ccrX.modify(|_, w| w.teie().clear_bit())
                                 }
                             }
                         }
                     }
+
+                    impl Channel for $CX {
+                        unsafe fn set_peripheral_address(&mut self, address: u32, inc: bool) {
+                            let dma = &*$DMAX::ptr();
+                            dma.$cparX.write(|w| w.pa().bits(address));
+                            dma.$ccrX.modify(|_, w| w.pinc().bit(inc));
+                        }
+
+                        unsafe fn set_memory_address(&mut self, address: u32, inc: bool) {
+                            let dma = &*$DMAX::ptr();
+                            dma.$cmarX.write(|w| w.ma().bits(address));
+                            dma.$ccrX.modify(|_, w| w.minc().bit(inc));
+                        }
+
+                        unsafe fn set_transfer_length(&mut self, len: u16) {
+                            (*$DMAX::ptr()).$cndtrX.write(|w| w.ndt().bits(len))
+                        }
+
+                        fn remaining(&self) -> u16 {
+                            // NOTE(unsafe) atomic read with no side effects
+                            unsafe { (*$DMAX::ptr()).$cndtrX.read().ndt().bits() }
+                        }
+
+                        fn set_priority(&mut self, priority: Priority) {
+                            unsafe {
+                                (*$DMAX::ptr()).$ccrX.modify(|_, w| w.pl().bits(priority as u8))
+                            }
+                        }
+
+                        unsafe fn configure(
+                            &mut self,
+                            dir: Direction,
+                            psize: WordSize,
+                            msize: WordSize,
+                            circular: bool,
+                        ) {
+                            (*$DMAX::ptr()).$ccrX.modify(|_, w| {
+                                w.dir()
+                                    .bit(dir != Direction::PeripheralToMemory)
+                                    .psize()
+                                    .bits(psize as u8)
+                                    .msize()
+                                    .bits(msize as u8)
+                                    .circ()
+                                    .bit(circular)
+                                    .mem2mem()
+                                    .bit(dir == Direction::MemoryToMemory)
+                            })
+                        }
+
+                        unsafe fn start(&mut self) {
+                            let dma = &*$DMAX::ptr();
+                            dma.ifcr.write(|w| w.$cgifX().set_bit());
+                            dma.$ccrX.modify(|_, w| w.en().set_bit());
+                        }
+
+                        fn stop(&mut self) {
+                            unsafe {
+                                let dma = &*$DMAX::ptr();
+                                dma.$ccrX.modify(|_, w| w.en().clear_bit());
+                                dma.ifcr.write(|w| w.$cgifX().set_bit());
+                            }
+                        }
+
+                        fn is_event(&self, event: Event) -> bool {
+                            // NOTE(unsafe) atomic read with no side effects
+                            let isr = unsafe { (*$DMAX::ptr()).isr.read() };
+                            match event {
+                                Event::HalfTransfer => isr.$htifX().bit_is_set(),
+                                Event::TransferComplete => isr.$tcifX().bit_is_set(),
+                                Event::TransferError => isr.$teifX().bit_is_set(),
+                            }
+                        }
+
+                        fn clear_event(&mut self, event: Event) {
+                            // NOTE(unsafe) atomic write to a stateless register
+                            unsafe {
+                                (*$DMAX::ptr()).ifcr.write(|w| match event {
+                                    Event::HalfTransfer => w.$chtifX().set_bit(),
+                                    Event::TransferComplete => w.$ctcifX().set_bit(),
+                                    Event::TransferError => w.$cteifX().set_bit(),
+                                })
+                            }
+                        }
+                    }
                 )+
 
                 impl DmaExt for $DMAX {
@@ -100,56 +811,56 @@ dma! {
             cndtr1, CNDTR1,
             cpar1, CPAR1,
             cmar1, CMAR1,
-            htif1, tcif1,
-            chtif1, ctcif1, cgif1
+            htif1, tcif1, teif1,
+            chtif1, ctcif1, cteif1, cgif1
         ),
         C2: (
             ccr2, CCR2,
             cndtr2, CNDTR2,
             cpar2, CPAR2,
             cmar2, CMAR2,
-            htif2, tcif2,
-            chtif2, ctcif2, cgif2
+            htif2, tcif2, teif2,
+            chtif2, ctcif2, cteif2, cgif2
         ),
         C3: (
             ccr3, CCR3,
             cndtr3, CNDTR3,
             cpar3, CPAR3,
             cmar3, CMAR3,
-            htif3, tcif3,
-            chtif3, ctcif3, cgif3
+            htif3, tcif3, teif3,
+            chtif3, ctcif3, cteif3, cgif3
         ),
         C4: (
             ccr4, CCR4,
             cndtr4, CNDTR4,
             cpar4, CPAR4,
             cmar4, CMAR4,
-            htif4, tcif4,
-            chtif4, ctcif4, cgif4
+            htif4, tcif4, teif4,
+            chtif4, ctcif4, cteif4, cgif4
         ),
         C5: (
             ccr5, CCR5,
             cndtr5, CNDTR5,
             cpar5, CPAR5,
             cmar5, CMAR5,
-            htif5, tcif5,
-            chtif5, ctcif5, cgif5
+            htif5, tcif5, teif5,
+            chtif5, ctcif5, cteif5, cgif5
         ),
         C6: (
             ccr6, CCR6,
             cndtr6, CNDTR6,
             cpar6, CPAR6,
             cmar6, CMAR6,
-            htif6, tcif6,
-            chtif6, ctcif6, cgif6
+            htif6, tcif6, teif6,
+            chtif6, ctcif6, cteif6, cgif6
         ),
         C7: (
             ccr7, CCR7,
             cndtr7, CNDTR7,
             cpar7, CPAR7,
             cmar7, CMAR7,
-            htif7, tcif7,
-            chtif7, ctcif7, cgif7
+            htif7, tcif7, teif7,
+            chtif7, ctcif7, cteif7, cgif7
         ),
     }),
 }
@@ -161,40 +872,88 @@ dma! {
             cndtr1, CNDTR1,
             cpar1, CPAR1,
             cmar1, CMAR1,
-            htif1, tcif1,
-            chtif1, ctcif1, cgif1
+            htif1, tcif1, teif1,
+            chtif1, ctcif1, cteif1, cgif1
         ),
         C2: (
             ccr2, CCR2,
             cndtr2, CNDTR2,
             cpar2, CPAR2,
             cmar2, CMAR2,
-            htif2, tcif2,
-            chtif2, ctcif2, cgif2
+            htif2, tcif2, teif2,
+            chtif2, ctcif2, cteif2, cgif2
         ),
         C3: (
             ccr3, CCR3,
             cndtr3, CNDTR3,
             cpar3, CPAR3,
             cmar3, CMAR3,
-            htif3, tcif3,
-            chtif3, ctcif3, cgif3
+            htif3, tcif3, teif3,
+            chtif3, ctcif3, cteif3, cgif3
         ),
         C4: (
             ccr4, CCR4,
             cndtr4, CNDTR4,
             cpar4, CPAR4,
             cmar4, CMAR4,
-            htif4, tcif4,
-            chtif4, ctcif4, cgif4
+            htif4, tcif4, teif4,
+            chtif4, ctcif4, cteif4, cgif4
         ),
         C5: (
             ccr5, CCR5,
             cndtr5, CNDTR5,
             cpar5, CPAR5,
             cmar5, CMAR5,
-            htif5, tcif5,
-            chtif5, ctcif5, cgif5
+            htif5, tcif5, teif5,
+            chtif5, ctcif5, cteif5, cgif5
         ),
     }),
 }
+
+macro_rules! requests {
+    ($($REQUEST:ident<$PERIPH:ty>: $CX:ty,)+) => {
+        $(
+            impl $REQUEST<$PERIPH> for $CX {}
+        )+
+    }
+}
+
+requests! {
+    RxChannel<ADC>: dma1::C1,
+    TxChannel<dac::C1>: dma1::C2,
+    TxChannel<dac::C2>: dma1::C3,
+
+    RxChannel<SPI1>: dma1::C2,
+    TxChannel<SPI1>: dma1::C3,
+    RxChannel<SPI2>: dma1::C4,
+    TxChannel<SPI2>: dma1::C5,
+    RxChannel<SPI3>: dma2::C1,
+    TxChannel<SPI3>: dma2::C2,
+
+    TxChannel<I2C1>: dma1::C6,
+    RxChannel<I2C1>: dma1::C7,
+    TxChannel<I2C2>: dma1::C4,
+    RxChannel<I2C2>: dma1::C5,
+
+    TxChannel<USART1>: dma1::C4,
+    RxChannel<USART1>: dma1::C5,
+    RxChannel<USART2>: dma1::C6,
+    TxChannel<USART2>: dma1::C7,
+    TxChannel<USART3>: dma1::C2,
+    RxChannel<USART3>: dma1::C3,
+
+    UpdateChannel<TIM2>: dma1::C2,
+    UpdateChannel<TIM3>: dma1::C3,
+    UpdateChannel<TIM4>: dma1::C7,
+    UpdateChannel<TIM5>: dma2::C2,
+    UpdateChannel<TIM6>: dma1::C2,
+    UpdateChannel<TIM7>: dma1::C3,
+}
+
+#[cfg(any(feature = "stm32l151", feature = "stm32l152", feature = "stm32l162"))]
+requests! {
+    RxChannel<UART4>: dma2::C3,
+    TxChannel<UART4>: dma2::C5,
+    TxChannel<UART5>: dma2::C1,
+    RxChannel<UART5>: dma2::C2,
+}
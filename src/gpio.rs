@@ -306,6 +306,25 @@ macro_rules! gpio {
                             });
                         }
                     }
+
+                    #[allow(dead_code)]
+                    pub(crate) fn set_output_mode(&self) {
+                        let offset = 2 * $i;
+                        unsafe {
+                            (*$GPIOX::ptr()).moder.modify(|r, w| {
+                                w.bits((r.bits() & !(0b11 << offset)) | (0b01 << offset))
+                            });
+                        }
+                    }
+
+                    #[allow(dead_code)]
+                    pub(crate) fn set_open_drain(&self) {
+                        unsafe {
+                            (*$GPIOX::ptr()).otyper.modify(|r, w| {
+                                w.bits(r.bits() | (0b1 << $i))
+                            });
+                        }
+                    }
                 }
 
                 impl<MODE> $PXi<Output<MODE>> {
@@ -1,9 +1,15 @@
 //! I2C
-use hal::blocking::i2c::{Read, Write, WriteRead};
+use core::cmp;
 
+use cortex_m::asm;
+use hal::blocking::i2c::{
+    Operation, Read, SevenBitAddress, Transactional, Write, WriteIter, WriteIterRead, WriteRead,
+};
+use hal::digital::v2::{InputPin, OutputPin};
+
+use crate::dma;
 use crate::gpio::gpiob::{PB10, PB11, PB6, PB7, PB8, PB9};
 use crate::gpio::{AltMode, OpenDrain, Output};
-use crate::prelude::*;
 use crate::rcc::Rcc;
 use crate::stm32::{I2C1, I2C2};
 use crate::time::Hertz;
@@ -12,48 +18,360 @@ use crate::time::Hertz;
 pub struct I2c<I2C, PINS> {
     i2c: I2C,
     pins: PINS,
+    timeout: u32,
 }
 
+/// Default number of status register polls before a transfer times out
+const DEFAULT_TIMEOUT: u32 = 100_000;
+
 pub trait Pins<I2c> {
     fn setup(&self);
+
+    /// Clocks SCL as a GPIO until SDA is released, then ends with a STOP
+    /// condition and hands the pins back to the peripheral
+    ///
+    /// Returns `false` if SDA is still held low after nine clock pulses.
+    fn recover(&mut self, half_period: u32) -> bool;
 }
 
-impl Pins<I2C1> for (PB6<Output<OpenDrain>>, PB7<Output<OpenDrain>>) {
-    fn setup(&self) {
-        self.0.set_alt_mode(AltMode::I2C);
-        self.1.set_alt_mode(AltMode::I2C);
+macro_rules! pins {
+    ($($I2CX:ty: ($SCL:ident, $SDA:ident),)+) => {
+        $(
+            impl Pins<$I2CX> for ($SCL<Output<OpenDrain>>, $SDA<Output<OpenDrain>>) {
+                fn setup(&self) {
+                    self.0.set_alt_mode(AltMode::I2C);
+                    self.1.set_alt_mode(AltMode::I2C);
+                }
+
+                fn recover(&mut self, half_period: u32) -> bool {
+                    let (scl, sda) = self;
+                    scl.set_high().ok();
+                    sda.set_high().ok();
+                    scl.set_output_mode();
+                    sda.set_output_mode();
+                    asm::delay(half_period);
+
+                    // A slave holding SDA releases it once it has clocked
+                    // out the rest of its byte
+                    for _ in 0..9 {
+                        if sda.is_high().unwrap_or(false) {
+                            break;
+                        }
+                        scl.set_low().ok();
+                        asm::delay(half_period);
+                        scl.set_high().ok();
+                        asm::delay(half_period);
+                    }
+                    let released = sda.is_high().unwrap_or(false);
+
+                    // STOP condition: SDA rises while SCL is high
+                    scl.set_low().ok();
+                    asm::delay(half_period);
+                    sda.set_low().ok();
+                    asm::delay(half_period);
+                    scl.set_high().ok();
+                    asm::delay(half_period);
+                    sda.set_high().ok();
+                    asm::delay(half_period);
+
+                    self.setup();
+                    released
+                }
+            }
+        )+
     }
 }
 
-impl Pins<I2C1> for (PB8<Output<OpenDrain>>, PB9<Output<OpenDrain>>) {
-    fn setup(&self) {
-        self.0.set_alt_mode(AltMode::I2C);
-        self.1.set_alt_mode(AltMode::I2C);
+pins! {
+    I2C1: (PB6, PB7),
+    I2C1: (PB8, PB9),
+    I2C2: (PB10, PB11),
+}
+
+/// DMA transfer from memory to an I2C slave
+pub struct WriteTransfer<I2C, PINS, CHANNEL, BUFFER> {
+    i2c: I2c<I2C, PINS>,
+    transfer: dma::Transfer<CHANNEL, BUFFER>,
+}
+
+/// DMA transfer from an I2C slave to memory
+pub struct ReadTransfer<I2C, PINS, CHANNEL, BUFFER> {
+    i2c: I2c<I2C, PINS>,
+    transfer: dma::Transfer<CHANNEL, BUFFER>,
+    len: usize,
+}
+
+/// Interrupt driven I2C master
+///
+/// Call `handle_event` from the I2C event interrupt and `handle_error` from
+/// the I2C error interrupt. Both return the outcome once the transaction
+/// finished, `take` then hands the buffers back.
+pub struct I2cMaster<I2C, PINS> {
+    i2c: I2c<I2C, PINS>,
+    transaction: Option<Transaction>,
+    phase: Phase,
+    index: usize,
+}
+
+/// Transaction run by an [`I2cMaster`] with a 7-bit address
+///
+/// The bytes of `write` are sent first, then `read` is filled after a
+/// repeated START. Either buffer may be empty.
+pub struct Transaction {
+    pub address: u8,
+    pub write: &'static [u8],
+    pub read: &'static mut [u8],
+}
+
+/// Progress of an interrupt driven transaction
+#[derive(Clone, Copy, PartialEq)]
+enum Phase {
+    Idle,
+    /// START requested, the address follows once SB is set
+    Start {
+        read: bool,
+    },
+    /// Address sent, waiting for ADDR
+    Address {
+        read: bool,
+    },
+    Write,
+    Read,
+    Done(Result<(), Error>),
+}
+
+/// I2C slave abstraction
+///
+/// Call `handle_event` from the I2C event interrupt and `handle_error`
+/// from the I2C error interrupt.
+pub struct I2cSlave<I2C, PINS> {
+    i2c: I2C,
+    pins: PINS,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /// Overrun or underrun of the data register, or an empty read buffer
+    Overrun,
+    /// The address was not acknowledged
+    NackAddress,
+    /// A data byte was not acknowledged
+    NackData,
+    /// Another master won the bus
+    ArbitrationLoss,
+    /// Misplaced START or STOP condition
+    Bus,
+    /// The bus did not respond within the configured timeout
+    Timeout,
+    /// The DMA channel rejected the buffer or reported a transfer error
+    Dma,
+}
+
+/// Fast mode SCL low to high period ratio
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DutyCycle {
+    Ratio2to1,
+    Ratio16to9,
+}
+
+/// Bus speed mode
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Up to 100 kHz
+    Standard { frequency: Hertz },
+    /// Up to 400 kHz
+    Fast {
+        frequency: Hertz,
+        duty_cycle: DutyCycle,
+    },
+}
+
+impl Mode {
+    pub fn standard<F: Into<Hertz>>(frequency: F) -> Self {
+        Mode::Standard {
+            frequency: frequency.into(),
+        }
+    }
+
+    pub fn fast<F: Into<Hertz>>(frequency: F, duty_cycle: DutyCycle) -> Self {
+        Mode::Fast {
+            frequency: frequency.into(),
+            duty_cycle,
+        }
     }
 }
 
-impl Pins<I2C2> for (PB10<Output<OpenDrain>>, PB11<Output<OpenDrain>>) {
-    fn setup(&self) {
-        self.0.set_alt_mode(AltMode::I2C);
-        self.1.set_alt_mode(AltMode::I2C);
+/// Picks standard mode up to 100 kHz and fast mode with a 2:1 duty cycle above
+impl From<Hertz> for Mode {
+    fn from(frequency: Hertz) -> Self {
+        if frequency.0 <= 100_000 {
+            Mode::standard(frequency)
+        } else {
+            Mode::fast(frequency, DutyCycle::Ratio2to1)
+        }
     }
 }
 
+/// Reason an I2C configuration was rejected
 #[derive(Debug)]
-pub enum Error {
-    OVERRUN,
-    NACK,
+pub enum InvalidConfig {
+    /// The APB1 clock is outside the 2 to 32 MHz range
+    BusClock,
+    /// Fast mode needs an APB1 clock of at least 4 MHz
+    FastModeBusClock,
+    /// The bus speed is zero or above the limit of the mode
+    Speed,
+    /// The bus speed cannot be derived from the APB1 clock
+    ClockDivider,
+}
+
+/// Register settings for a bus speed
+struct Timing {
+    freq: u8,
+    trise: u8,
+    ccr: u16,
+    fast: bool,
+    duty: bool,
+}
+
+/// Computes the clock control and rise time settings for `mode`
+///
+/// The divider is rounded up so the bus never runs faster than requested.
+fn timing(pclk: u32, mode: Mode) -> Result<Timing, InvalidConfig> {
+    let freq = pclk / 1_000_000;
+    if !(2..=32).contains(&freq) {
+        return Err(InvalidConfig::BusClock);
+    }
+
+    let (frequency, limit, divisor, min, fast, duty) = match mode {
+        Mode::Standard { frequency } => (frequency.0, 100_000, 2, 4, false, false),
+        Mode::Fast {
+            frequency,
+            duty_cycle,
+        } => {
+            if freq < 4 {
+                return Err(InvalidConfig::FastModeBusClock);
+            }
+            match duty_cycle {
+                DutyCycle::Ratio2to1 => (frequency.0, 400_000, 3, 4, true, false),
+                DutyCycle::Ratio16to9 => (frequency.0, 400_000, 25, 1, true, true),
+            }
+        }
+    };
+    if frequency == 0 || frequency > limit {
+        return Err(InvalidConfig::Speed);
+    }
+
+    let ccr = pclk.div_ceil(frequency * divisor);
+    if !(min..=0xfff).contains(&ccr) {
+        return Err(InvalidConfig::ClockDivider);
+    }
+
+    // Maximum rise time is 1000 ns in standard mode and 300 ns in fast mode
+    let trise = if fast {
+        freq * 300 / 1000 + 1
+    } else {
+        freq + 1
+    };
+
+    Ok(Timing {
+        freq: freq as u8,
+        trise: trise as u8,
+        ccr: ccr as u16,
+        fast,
+        duty,
+    })
+}
+
+/// Target address of a master transfer
+#[derive(Clone, Copy)]
+enum Address {
+    SevenBit(u8),
+    TenBit(u16),
+}
+
+/// Own slave address
+#[derive(Clone, Copy, Debug)]
+pub enum OwnAddress {
+    SevenBit(u8),
+    TenBit(u16),
+}
+
+/// Slave configuration
+pub struct SlaveConfig {
+    pub address: OwnAddress,
+    /// Second 7-bit address, only available with a 7-bit own address
+    pub secondary_address: Option<u8>,
+    /// Respond to the general call address
+    pub general_call: bool,
+}
+
+impl SlaveConfig {
+    pub fn new(address: OwnAddress) -> Self {
+        SlaveConfig {
+            address,
+            secondary_address: None,
+            general_call: false,
+        }
+    }
+
+    pub fn secondary_address(mut self, address: u8) -> Self {
+        self.secondary_address = Some(address);
+        self
+    }
+
+    pub fn general_call(mut self, general_call: bool) -> Self {
+        self.general_call = general_call;
+        self
+    }
+}
+
+/// Address a slave responded to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MatchedAddress {
+    Primary,
+    Secondary,
+    GeneralCall,
+}
+
+/// Slave transfer direction as seen from the slave
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SlaveDirection {
+    /// The master writes to the slave
+    Receive,
+    /// The master reads from the slave
+    Transmit,
+}
+
+/// Slave event
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SlaveEvent {
+    /// The slave was addressed by a master
+    AddressMatch(MatchedAddress, SlaveDirection),
+    /// A byte was received from the master
+    Received(u8),
+    /// The master waits for the next byte, reply with `send`
+    TransmitRequest,
+    /// A STOP condition ended the transfer
+    Stop,
 }
 
 macro_rules! i2c {
     ($I2CX:ident, $i2cx:ident, $i2cxen:ident, $i2crst:ident) => {
         impl<PINS> I2c<$I2CX, PINS> {
-            pub fn $i2cx(i2c: $I2CX, pins: PINS, speed: Hertz, rcc: &mut Rcc) -> Self
+            pub fn $i2cx<M>(
+                i2c: $I2CX,
+                pins: PINS,
+                mode: M,
+                rcc: &mut Rcc,
+            ) -> Result<Self, InvalidConfig>
             where
                 PINS: Pins<$I2CX>,
+                M: Into<Mode>,
             {
+                let timing = timing(rcc.clocks.apb1_clk().0, mode.into())?;
+
                 pins.setup();
-                let speed: Hertz = speed.into();
 
                 // Enable clock for I2C
                 rcc.rb.apb1enr.modify(|_, w| w.$i2cxen().set_bit());
@@ -65,254 +383,1182 @@ macro_rules! i2c {
                 // Make sure the I2C unit is disabled so we can configure it
                 i2c.cr1.modify(|_, w| w.pe().clear_bit());
 
-                // Calculate settings for I2C speed modes
-                let clock = rcc.clocks.apb1_clk().0;
-                let freq = clock / 1_000_000;
-                assert!(freq >= 2 && freq <= 50);
-
                 // Configure bus frequency into I2C peripheral
-                i2c.cr2.write(|w| unsafe { w.freq().bits(freq as u8) });
-
-                let trise = if speed <= 100_u32.khz().into() {
-                    freq + 1
-                } else {
-                    (freq * 300) / 1000 + 1
-                };
+                i2c.cr2.write(|w| unsafe { w.freq().bits(timing.freq) });
 
                 // Configure correct rise times
-                i2c.trise.write(|w| w.trise().bits(trise as u8));
-
-                // I2C clock control calculation
-                if speed <= 100_u32.khz().into() {
-                    let ccr = {
-                        let ccr = clock / (speed.0 * 2);
-                        if ccr < 4 {
-                            4
-                        } else {
-                            ccr
-                        }
-                    };
-
-                    // Set clock to standard mode with appropriate parameters for selected speed
-                    i2c.ccr.write(|w| unsafe {
-                        w.f_s()
-                            .clear_bit()
-                            .duty()
-                            .clear_bit()
-                            .ccr()
-                            .bits(ccr as u16)
-                    });
-                } else {
-                    const DUTYCYCLE: u8 = 0;
-                    if DUTYCYCLE == 0 {
-                        let ccr = clock / (speed.0 * 3);
-                        let ccr = if ccr < 1 { 1 } else { ccr };
-
-                        // Set clock to fast mode with appropriate parameters for selected speed (2:1 duty cycle)
-                        i2c.ccr.write(|w| unsafe {
-                            w.f_s().set_bit().duty().clear_bit().ccr().bits(ccr as u16)
-                        });
-                    } else {
-                        let ccr = clock / (speed.0 * 25);
-                        let ccr = if ccr < 1 { 1 } else { ccr };
+                i2c.trise.write(|w| w.trise().bits(timing.trise));
 
-                        // Set clock to fast mode with appropriate parameters for selected speed (16:9 duty cycle)
-                        i2c.ccr.write(|w| unsafe {
-                            w.f_s().set_bit().duty().set_bit().ccr().bits(ccr as u16)
-                        });
-                    }
-                }
+                // Set clock to standard or fast mode with appropriate parameters for selected speed
+                i2c.ccr.write(|w| unsafe {
+                    w.f_s()
+                        .bit(timing.fast)
+                        .duty()
+                        .bit(timing.duty)
+                        .ccr()
+                        .bits(timing.ccr)
+                });
 
                 // Enable the I2C processing
                 i2c.cr1.modify(|_, w| w.pe().set_bit());
 
-                I2c { i2c, pins }
+                Ok(I2c {
+                    i2c,
+                    pins,
+                    timeout: DEFAULT_TIMEOUT,
+                })
+            }
+
+            /// Sets how many times a status flag is polled before giving up
+            pub fn set_timeout(&mut self, polls: u32) {
+                self.timeout = polls;
             }
 
             pub fn release(self) -> ($I2CX, PINS) {
                 (self.i2c, self.pins)
             }
 
-            fn write_bytes(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Error> {
+            /// Frees a bus stuck by a slave holding SDA low and resets the
+            /// peripheral
+            ///
+            /// The pins are driven as GPIOs at about 100 kHz for up to nine
+            /// SCL pulses followed by a STOP condition. The configuration is
+            /// kept across the software reset. Returns `Error::Bus` if SDA
+            /// is still held low.
+            pub fn recover(&mut self, rcc: &Rcc) -> Result<(), Error>
+            where
+                PINS: Pins<$I2CX>,
+            {
+                self.i2c.cr1.modify(|_, w| w.pe().clear_bit());
+
+                let half_period = rcc.clocks.sys_clk().0 / 200_000;
+                let released = self.pins.recover(cmp::max(half_period, 1));
+
+                // SWRST clears every register, keep the configuration
+                let cr2 = self.i2c.cr2.read().bits();
+                let ccr = self.i2c.ccr.read().bits();
+                let trise = self.i2c.trise.read().bits();
+                let oar1 = self.i2c.oar1.read().bits();
+                let oar2 = self.i2c.oar2.read().bits();
+
+                self.i2c.cr1.write(|w| w.swrst().set_bit());
+                self.i2c.cr1.reset();
+
+                self.i2c.cr2.write(|w| unsafe { w.bits(cr2) });
+                self.i2c.ccr.write(|w| unsafe { w.bits(ccr) });
+                self.i2c.trise.write(|w| unsafe { w.bits(trise) });
+                self.i2c.oar1.write(|w| unsafe { w.bits(oar1) });
+                self.i2c.oar2.write(|w| unsafe { w.bits(oar2) });
+                self.i2c.cr1.modify(|_, w| w.pe().set_bit());
+
+                if released && self.i2c.sr2.read().busy().bit_is_clear() {
+                    Ok(())
+                } else {
+                    Err(Error::Bus)
+                }
+            }
+
+            /// Writes `bytes` to the slave with the 10-bit address `addr`
+            pub fn write_10bit(&mut self, addr: u16, bytes: &[u8]) -> Result<(), Error> {
+                self.write_bytes(Address::TenBit(addr), bytes.iter().cloned())?;
+
+                // Send a STOP condition
+                self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+
+                Ok(())
+            }
+
+            /// Fills `buffer` from the slave with the 10-bit address `addr`
+            pub fn read_10bit(&mut self, addr: u16, buffer: &mut [u8]) -> Result<(), Error> {
+                self.read_bytes(Address::TenBit(addr), buffer, true)
+            }
+
+            /// Writes `bytes` to the slave with the 10-bit address `addr`,
+            /// then fills `buffer` after a repeated START
+            pub fn write_read_10bit(
+                &mut self,
+                addr: u16,
+                bytes: &[u8],
+                buffer: &mut [u8],
+            ) -> Result<(), Error> {
+                self.write_read(Address::TenBit(addr), bytes.iter().cloned(), buffer)
+            }
+
+            /// Runs `operations` on the slave with the 10-bit address `addr`
+            /// like `Transactional::exec`
+            pub fn exec_10bit(
+                &mut self,
+                addr: u16,
+                operations: &mut [Operation],
+            ) -> Result<(), Error> {
+                self.transaction(Address::TenBit(addr), operations)
+            }
+
+            /// Polls until `ready` returns true, reporting and clearing any
+            /// error flag set in the meantime
+            ///
+            /// A NACK is reported as `nack` and terminates the transfer with a
+            /// STOP condition, as does a timeout.
+            fn wait<F>(&self, nack: Error, ready: F) -> Result<(), Error>
+            where
+                F: Fn(&$I2CX) -> bool,
+            {
+                for _ in 0..self.timeout {
+                    self.check_errors(nack)?;
+
+                    if ready(&self.i2c) {
+                        return Ok(());
+                    }
+                }
+
+                self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                Err(Error::Timeout)
+            }
+
+            /// Reports and clears a pending error flag, a NACK is reported as
+            /// `nack` and terminates the transfer with a STOP condition
+            fn check_errors(&self, nack: Error) -> Result<(), Error> {
+                let sr1 = self.i2c.sr1.read();
+
+                if sr1.arlo().bit_is_set() {
+                    // The peripheral already switched back to slave mode
+                    self.i2c.sr1.modify(|_, w| w.arlo().clear_bit());
+                    Err(Error::ArbitrationLoss)
+                } else if sr1.berr().bit_is_set() {
+                    self.i2c.sr1.modify(|_, w| w.berr().clear_bit());
+                    Err(Error::Bus)
+                } else if sr1.af().bit_is_set() {
+                    self.i2c.sr1.modify(|_, w| w.af().clear_bit());
+                    self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                    Err(nack)
+                } else if sr1.ovr().bit_is_set() {
+                    self.i2c.sr1.modify(|_, w| w.ovr().clear_bit());
+                    Err(Error::Overrun)
+                } else {
+                    Ok(())
+                }
+            }
+
+            /// Sends a START condition followed by the address
+            fn start(&mut self, addr: Address, read: bool) -> Result<(), Error> {
                 // Send a START condition
                 self.i2c.cr1.modify(|_, w| w.start().set_bit());
 
+                self.address(addr, read)
+            }
+
+            /// Sends the address once a requested START condition was generated
+            fn address(&mut self, addr: Address, read: bool) -> Result<(), Error> {
                 // Wait until START condition was generated
-                while {
-                    let sr1 = self.i2c.sr1.read();
-                    sr1.sb().bit_is_clear()
-                } {}
+                self.wait(Error::NackAddress, |i2c| i2c.sr1.read().sb().bit_is_set())?;
 
                 // Also wait until signalled we're master and everything is waiting for us
-                while {
-                    let sr2 = self.i2c.sr2.read();
-                    sr2.msl().bit_is_clear() && sr2.busy().bit_is_clear()
-                } {}
+                self.wait(Error::NackAddress, |i2c| {
+                    let sr2 = i2c.sr2.read();
+                    sr2.msl().bit_is_set() || sr2.busy().bit_is_set()
+                })?;
 
-                // Set up current address, we're trying to talk to
-                self.i2c
-                    .dr
-                    .write(|w| unsafe { w.bits(u32::from(addr) << 1) });
+                match addr {
+                    Address::SevenBit(addr) => {
+                        // Set up current address, we're trying to talk to
+                        let byte = (addr << 1) | read as u8;
+                        self.i2c.dr.write(|w| unsafe { w.bits(u32::from(byte)) });
+                    }
+                    Address::TenBit(addr) => {
+                        // The header carries the two upper address bits
+                        let header = 0xf0 | ((addr >> 7) as u8 & 0x06);
+                        self.i2c.dr.write(|w| unsafe { w.bits(u32::from(header)) });
+                        self.wait(Error::NackAddress, |i2c| {
+                            i2c.sr1.read().add10().bit_is_set()
+                        })?;
+
+                        self.i2c
+                            .dr
+                            .write(|w| unsafe { w.bits(u32::from(addr & 0xff)) });
+
+                        if read {
+                            // Reading requires a repeated START with the
+                            // header in read direction
+                            self.wait(Error::NackAddress, |i2c| {
+                                i2c.sr1.read().addr().bit_is_set()
+                            })?;
+                            self.i2c.sr2.read();
+                            self.i2c.cr1.modify(|_, w| w.start().set_bit());
+                            self.wait(Error::NackAddress, |i2c| i2c.sr1.read().sb().bit_is_set())?;
+                            self.i2c
+                                .dr
+                                .write(|w| unsafe { w.bits(u32::from(header | 1)) });
+                        }
+                    }
+                }
 
                 // Wait until address was sent
-                while {
-                    let sr1 = self.i2c.sr1.read();
-                    sr1.addr().bit_is_clear()
-                } {}
+                self.wait(Error::NackAddress, |i2c| i2c.sr1.read().addr().bit_is_set())?;
 
                 // Clear condition by reading SR2
                 self.i2c.sr2.read();
 
+                Ok(())
+            }
+
+            fn write_bytes<B>(&mut self, addr: Address, bytes: B) -> Result<(), Error>
+            where
+                B: IntoIterator<Item = u8>,
+            {
+                self.start(addr, false)?;
+
                 // Send bytes
                 for c in bytes {
-                    self.send_byte(*c)?;
+                    self.send_byte(c)?;
                 }
 
                 // Fallthrough is success
                 Ok(())
             }
 
-            fn send_byte(&self, byte: u8) -> Result<(), Error> {
-                // Wait until we're ready for sending
-                while self.i2c.sr1.read().tx_e().bit_is_clear() {}
+            /// Receives into `buffer`, ending with a STOP condition or, if
+            /// `stop` is false, a repeated START for the next transfer
+            fn read_bytes<'b, B>(
+                &mut self,
+                addr: Address,
+                buffer: B,
+                stop: bool,
+            ) -> Result<(), Error>
+            where
+                B: IntoIterator<Item = &'b mut u8>,
+            {
+                let mut buffer = buffer.into_iter().peekable();
+                if buffer.peek().is_none() {
+                    return Err(Error::Overrun);
+                }
 
-                // Push out a byte of data
-                self.i2c.dr.write(|w| unsafe { w.bits(u32::from(byte)) });
+                // Set ACK bit for all but the last byte
+                self.i2c.cr1.modify(|_, w| w.ack().set_bit());
 
-                // While until byte is transferred
-                while {
-                    let sr1 = self.i2c.sr1.read();
+                self.start(addr, true)?;
 
-                    // If we received a NACK, then this is an error
-                    if sr1.af().bit_is_set() {
-                        return Err(Error::NACK);
+                while let Some(c) = buffer.next() {
+                    if buffer.peek().is_none() {
+                        // Prepare to send NACK then STOP or START after next byte
+                        self.i2c
+                            .cr1
+                            .modify(|_, w| w.ack().clear_bit().stop().bit(stop).start().bit(!stop));
                     }
+                    *c = self.recv_byte()?;
+                }
+
+                // Fallthrough is success
+                Ok(())
+            }
+
+            fn write_read<B>(
+                &mut self,
+                addr: Address,
+                bytes: B,
+                buffer: &mut [u8],
+            ) -> Result<(), Error>
+            where
+                B: IntoIterator<Item = u8>,
+            {
+                let mut bytes = bytes.into_iter().peekable();
+                let write = bytes.peek().is_some();
+                if write {
+                    self.write_bytes(addr, bytes)?;
+                }
 
-                    sr1.btf().bit_is_clear()
-                } {}
+                if !buffer.is_empty() {
+                    self.read_bytes(addr, buffer, true)?;
+                } else if write {
+                    self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                }
 
                 Ok(())
             }
 
+            /// Runs `operations`, merging adjacent operations of the same
+            /// kind and separating the others with a repeated START
+            fn transaction(
+                &mut self,
+                addr: Address,
+                operations: &mut [Operation],
+            ) -> Result<(), Error> {
+                let mut restarted = false;
+                let mut rest = operations;
+                while let Some(first) = rest.first() {
+                    let reading = matches!(first, Operation::Read(_));
+                    let len = rest
+                        .iter()
+                        .position(|op| matches!(op, Operation::Read(_)) != reading)
+                        .unwrap_or(rest.len());
+                    let (run, tail) = rest.split_at_mut(len);
+                    let last = tail.is_empty();
+
+                    if reading {
+                        let buffer = run.iter_mut().flat_map(|op| match op {
+                            Operation::Read(buffer) => buffer.iter_mut(),
+                            Operation::Write(_) => [].iter_mut(),
+                        });
+                        self.read_bytes(addr, buffer, last)?;
+                        restarted = !last;
+                    } else {
+                        if restarted {
+                            self.address(addr, false)?;
+                        } else {
+                            self.start(addr, false)?;
+                        }
+                        for op in run.iter() {
+                            if let Operation::Write(bytes) = op {
+                                for c in bytes.iter() {
+                                    self.send_byte(*c)?;
+                                }
+                            }
+                        }
+                        restarted = false;
+                        if last {
+                            self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                        }
+                    }
+
+                    rest = tail;
+                }
+
+                Ok(())
+            }
+
+            fn send_byte(&self, byte: u8) -> Result<(), Error> {
+                // Wait until we're ready for sending
+                self.wait(Error::NackData, |i2c| i2c.sr1.read().tx_e().bit_is_set())?;
+
+                // Push out a byte of data
+                self.i2c.dr.write(|w| unsafe { w.bits(u32::from(byte)) });
+
+                // While until byte is transferred
+                self.wait(Error::NackData, |i2c| i2c.sr1.read().btf().bit_is_set())
+            }
+
             fn recv_byte(&self) -> Result<u8, Error> {
-                while self.i2c.sr1.read().rx_ne().bit_is_clear() {}
+                self.wait(Error::NackData, |i2c| i2c.sr1.read().rx_ne().bit_is_set())?;
                 let value = self.i2c.dr.read().bits() as u8;
                 Ok(value)
             }
         }
 
-        impl<PINS> WriteRead for I2c<$I2CX, PINS> {
-            type Error = Error;
+        impl<PINS> I2c<$I2CX, PINS> {
+            /// Sends the whole `buffer` to the slave at `addr` using DMA
+            ///
+            /// The address is sent before this returns, the transfer ends
+            /// with a STOP condition in `wait`. On failure the I2C, the
+            /// channel and the buffer are handed back with the error.
+            pub fn write_dma<CHANNEL, BUFFER>(
+                mut self,
+                addr: u8,
+                channel: CHANNEL,
+                buffer: BUFFER,
+            ) -> Result<
+                WriteTransfer<$I2CX, PINS, CHANNEL, BUFFER>,
+                (Error, I2c<$I2CX, PINS>, CHANNEL, BUFFER),
+            >
+            where
+                CHANNEL: dma::TxChannel<$I2CX>,
+                BUFFER: dma::ReadBuffer<Word = u8>,
+            {
+                let dr = &self.i2c.dr as *const _ as u32;
+                // NOTE(unsafe) `channel` serves the transmit requests of this I2C
+                let transfer = match unsafe {
+                    dma::Transfer::memory_to_peripheral(channel, buffer, dr)
+                } {
+                    Ok(transfer) => transfer,
+                    Err((_, channel, buffer)) => return Err((Error::Dma, self, channel, buffer)),
+                };
+                self.i2c.cr2.modify(|_, w| w.dmaen().set_bit());
 
-            fn write_read(
-                &mut self,
+                if let Err(error) = self.start(Address::SevenBit(addr), false) {
+                    let (i2c, channel, buffer) = WriteTransfer {
+                        i2c: self,
+                        transfer,
+                    }
+                    .abort();
+                    return Err((error, i2c, channel, buffer));
+                }
+
+                Ok(WriteTransfer {
+                    i2c: self,
+                    transfer,
+                })
+            }
+
+            /// Fills the whole `buffer` from the slave at `addr` using DMA
+            ///
+            /// The address is sent before this returns, the transfer ends
+            /// with a STOP condition in `wait`. On failure the I2C, the
+            /// channel and the buffer are handed back with the error.
+            pub fn read_dma<CHANNEL, BUFFER>(
+                mut self,
                 addr: u8,
-                bytes: &[u8],
-                buffer: &mut [u8],
-            ) -> Result<(), Self::Error> {
-                if !bytes.is_empty() {
-                    self.write_bytes(addr, bytes)?;
+                channel: CHANNEL,
+                mut buffer: BUFFER,
+            ) -> Result<
+                ReadTransfer<$I2CX, PINS, CHANNEL, BUFFER>,
+                (Error, I2c<$I2CX, PINS>, CHANNEL, BUFFER),
+            >
+            where
+                CHANNEL: dma::RxChannel<$I2CX>,
+                BUFFER: dma::WriteBuffer<Word = u8>,
+            {
+                let (_, len) = unsafe { buffer.write_buffer() };
+                let dr = &self.i2c.dr as *const _ as u32;
+                // NOTE(unsafe) `channel` serves the receive requests of this I2C
+                let transfer = match unsafe {
+                    dma::Transfer::peripheral_to_memory(channel, dr, buffer)
+                } {
+                    Ok(transfer) => transfer,
+                    Err((_, channel, buffer)) => return Err((Error::Dma, self, channel, buffer)),
+                };
+
+                match len {
+                    // NACK the only byte, STOP follows once the address is sent
+                    1 => self.i2c.cr1.modify(|_, w| w.ack().clear_bit()),
+                    // NACK the byte after the one in the shift register
+                    2 => self
+                        .i2c
+                        .cr1
+                        .modify(|_, w| w.ack().clear_bit().pos().set_bit()),
+                    // NACK the byte following the last but one DMA request
+                    _ => {
+                        self.i2c.cr1.modify(|_, w| w.ack().set_bit());
+                        self.i2c.cr2.modify(|_, w| w.last().set_bit());
+                    }
                 }
+                self.i2c.cr2.modify(|_, w| w.dmaen().set_bit());
 
-                if !buffer.is_empty() {
-                    self.read(addr, buffer)?;
-                } else if !bytes.is_empty() {
+                if let Err(error) = self.start(Address::SevenBit(addr), true) {
+                    let (i2c, channel, buffer) = ReadTransfer {
+                        i2c: self,
+                        transfer,
+                        len,
+                    }
+                    .abort();
+                    return Err((error, i2c, channel, buffer));
+                }
+
+                if len == 1 {
                     self.i2c.cr1.modify(|_, w| w.stop().set_bit());
                 }
 
+                Ok(ReadTransfer {
+                    i2c: self,
+                    transfer,
+                    len,
+                })
+            }
+
+            /// Returns `true` if an error flag is set, without clearing it
+            fn error_pending(&self) -> bool {
+                let sr1 = self.i2c.sr1.read();
+                sr1.arlo().bit_is_set()
+                    || sr1.berr().bit_is_set()
+                    || sr1.af().bit_is_set()
+                    || sr1.ovr().bit_is_set()
+            }
+
+            /// Polls until `transfer` is done, reporting any error flag
+            ///
+            /// The timeout restarts whenever the DMA moves a byte.
+            fn wait_dma<CHANNEL, BUFFER>(
+                &self,
+                transfer: &dma::Transfer<CHANNEL, BUFFER>,
+            ) -> Result<(), Error>
+            where
+                CHANNEL: dma::Channel,
+            {
+                let mut remaining = transfer.remaining();
+                let mut polls = 0;
+                while !transfer.is_done() {
+                    self.check_errors(Error::NackData)?;
+
+                    let left = transfer.remaining();
+                    if left != remaining {
+                        remaining = left;
+                        polls = 0;
+                    } else if polls == self.timeout {
+                        return Err(Error::Timeout);
+                    } else {
+                        polls += 1;
+                    }
+                }
+
                 Ok(())
             }
+
+            /// Clears the DMA related bits once a transfer is over
+            fn finish_dma(&self) {
+                self.i2c
+                    .cr2
+                    .modify(|_, w| w.dmaen().clear_bit().last().clear_bit());
+                self.i2c.cr1.modify(|_, w| w.pos().clear_bit());
+            }
+
+            /// Ends the transfer with a STOP condition unless the bus was lost
+            fn abort_dma(&self) {
+                if self.i2c.sr2.read().msl().bit_is_set() {
+                    self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                }
+                self.finish_dma();
+            }
         }
 
-        impl<PINS> Write for I2c<$I2CX, PINS> {
-            type Error = Error;
+        impl<PINS, CHANNEL, BUFFER> WriteTransfer<$I2CX, PINS, CHANNEL, BUFFER>
+        where
+            CHANNEL: dma::Channel,
+        {
+            /// Returns `true` if all bytes have been handed to the I2C or the
+            /// transfer failed
+            pub fn is_done(&self) -> bool {
+                self.transfer.is_done() || self.i2c.error_pending()
+            }
 
-            fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Self::Error> {
-                self.write_bytes(addr, bytes)?;
+            /// Blocks until all bytes have been sent and the STOP condition
+            /// was requested
+            ///
+            /// Fails with `Error::Timeout` if no byte is moved within the
+            /// configured number of polls. On failure the I2C, the channel
+            /// and the buffer are handed back with the error.
+            pub fn wait(
+                self,
+            ) -> Result<
+                (I2c<$I2CX, PINS>, CHANNEL, BUFFER),
+                (Error, I2c<$I2CX, PINS>, CHANNEL, BUFFER),
+            > {
+                if let Err(error) = self.i2c.wait_dma(&self.transfer) {
+                    let (i2c, channel, buffer) = self.abort();
+                    return Err((error, i2c, channel, buffer));
+                }
 
-                // Send a STOP condition
-                self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                // The last byte is still shifted out when the DMA is done
+                let (res, channel, buffer) = match self.transfer.wait() {
+                    Ok((channel, buffer)) => (
+                        self.i2c
+                            .wait(Error::NackData, |i2c| i2c.sr1.read().btf().bit_is_set()),
+                        channel,
+                        buffer,
+                    ),
+                    Err((_, channel, buffer)) => (Err(Error::Dma), channel, buffer),
+                };
+                match res {
+                    Ok(()) => {
+                        self.i2c.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                        self.i2c.finish_dma();
+                        Ok((self.i2c, channel, buffer))
+                    }
+                    Err(error) => {
+                        self.i2c.abort_dma();
+                        Err((error, self.i2c, channel, buffer))
+                    }
+                }
+            }
+
+            /// Stops the transfer and releases the I2C, the channel and the buffer
+            ///
+            /// A STOP condition is sent if the I2C still owns the bus.
+            pub fn abort(self) -> (I2c<$I2CX, PINS>, CHANNEL, BUFFER) {
+                let (channel, buffer) = self.transfer.abort();
+                self.i2c.abort_dma();
+                (self.i2c, channel, buffer)
+            }
+        }
+
+        impl<PINS, CHANNEL, BUFFER> ReadTransfer<$I2CX, PINS, CHANNEL, BUFFER>
+        where
+            CHANNEL: dma::Channel,
+        {
+            /// Returns `true` if the buffer has been filled or the transfer
+            /// failed
+            pub fn is_done(&self) -> bool {
+                self.transfer.is_done() || self.i2c.error_pending()
+            }
+
+            /// Blocks until the buffer has been filled and the STOP condition
+            /// was requested
+            ///
+            /// Fails with `Error::Timeout` if no byte is moved within the
+            /// configured number of polls. On failure the I2C, the channel
+            /// and the buffer are handed back with the error.
+            pub fn wait(
+                self,
+            ) -> Result<
+                (I2c<$I2CX, PINS>, CHANNEL, BUFFER),
+                (Error, I2c<$I2CX, PINS>, CHANNEL, BUFFER),
+            > {
+                if let Err(error) = self.i2c.wait_dma(&self.transfer) {
+                    let (i2c, channel, buffer) = self.abort();
+                    return Err((error, i2c, channel, buffer));
+                }
+
+                let res = self.transfer.wait();
+                // The last byte was NACKed, a single byte read already
+                // requested the STOP condition
+                if self.len > 1 {
+                    self.i2c.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                }
+                self.i2c.finish_dma();
+                match res {
+                    Ok((channel, buffer)) => Ok((self.i2c, channel, buffer)),
+                    Err((_, channel, buffer)) => Err((Error::Dma, self.i2c, channel, buffer)),
+                }
+            }
+
+            /// Stops the transfer and releases the I2C, the channel and the buffer
+            ///
+            /// A STOP condition is sent if the I2C still owns the bus.
+            pub fn abort(self) -> (I2c<$I2CX, PINS>, CHANNEL, BUFFER) {
+                let (channel, buffer) = self.transfer.abort();
+                self.i2c.abort_dma();
+                (self.i2c, channel, buffer)
+            }
+        }
+
+        impl<PINS> I2cMaster<$I2CX, PINS> {
+            pub fn new(i2c: I2c<$I2CX, PINS>) -> Self {
+                I2cMaster {
+                    i2c,
+                    transaction: None,
+                    phase: Phase::Idle,
+                    index: 0,
+                }
+            }
+
+            /// Starts `transaction`, the interrupts are enabled until it
+            /// finishes
+            ///
+            /// The transaction is handed back if the previous one was not
+            /// taken yet or the STOP condition ending it is still pending.
+            pub fn start(&mut self, transaction: Transaction) -> Result<(), Transaction> {
+                if self.transaction.is_some() {
+                    return Err(transaction);
+                }
+
+                let i2c = &self.i2c.i2c;
+                // The STOP condition ending the previous transfer may still
+                // be pending, it takes a bit period at most
+                let mut stopped = false;
+                for _ in 0..self.i2c.timeout {
+                    if i2c.cr1.read().stop().bit_is_clear() {
+                        stopped = true;
+                        break;
+                    }
+                }
+                if !stopped {
+                    return Err(transaction);
+                }
+
+                let read = transaction.write.is_empty() && !transaction.read.is_empty();
+                self.transaction = Some(transaction);
+                self.phase = Phase::Start { read };
+                self.index = 0;
+
+                i2c.cr1.modify(|_, w| w.pos().clear_bit().ack().set_bit());
+                i2c.cr2.modify(|_, w| {
+                    w.itevten()
+                        .set_bit()
+                        .itbufen()
+                        .set_bit()
+                        .iterren()
+                        .set_bit()
+                });
+                i2c.cr1.modify(|_, w| w.start().set_bit());
 
-                // Fallthrough is success
                 Ok(())
             }
+
+            /// Returns `true` if a transaction finished and can be taken
+            pub fn is_done(&self) -> bool {
+                matches!(self.phase, Phase::Done(_))
+            }
+
+            /// Returns the finished transaction and its outcome
+            ///
+            /// Returns `None` while the transaction is in progress or if
+            /// none was started.
+            pub fn take(&mut self) -> Option<(Transaction, Result<(), Error>)> {
+                match self.phase {
+                    Phase::Done(result) => {
+                        self.phase = Phase::Idle;
+                        self.transaction.take().map(|t| (t, result))
+                    }
+                    _ => None,
+                }
+            }
+
+            /// Advances the transaction on an event interrupt
+            ///
+            /// Returns the outcome once the transaction finished.
+            pub fn handle_event(&mut self) -> Option<Result<(), Error>> {
+                let transaction = self.transaction.as_mut()?;
+                let i2c = &self.i2c.i2c;
+                let sr1 = i2c.sr1.read();
+
+                match self.phase {
+                    Phase::Start { read } => {
+                        if sr1.sb().bit_is_set() {
+                            let byte = (transaction.address << 1) | read as u8;
+                            i2c.dr.write(|w| unsafe { w.bits(u32::from(byte)) });
+                            self.phase = Phase::Address { read };
+                        }
+                        None
+                    }
+                    Phase::Address { read: false } => {
+                        if sr1.addr().bit_is_set() {
+                            // Reading SR2 after SR1 clears ADDR
+                            i2c.sr2.read();
+                            self.phase = Phase::Write;
+                            if transaction.write.is_empty() {
+                                return self.end_write();
+                            }
+                        }
+                        None
+                    }
+                    Phase::Address { read: true } => {
+                        if sr1.addr().bit_is_set() {
+                            // The NACK has to be prepared before ADDR is
+                            // cleared, as reception starts right after
+                            match transaction.read.len() {
+                                1 => {
+                                    i2c.cr1.modify(|_, w| w.ack().clear_bit());
+                                    i2c.sr2.read();
+                                    i2c.cr1.modify(|_, w| w.stop().set_bit());
+                                }
+                                2 => {
+                                    // NACK the byte after the one in the
+                                    // shift register, then wait for both
+                                    i2c.cr1.modify(|_, w| w.ack().clear_bit().pos().set_bit());
+                                    i2c.sr2.read();
+                                    i2c.cr2.modify(|_, w| w.itbufen().clear_bit());
+                                }
+                                len => {
+                                    i2c.sr2.read();
+                                    if len == 3 {
+                                        i2c.cr2.modify(|_, w| w.itbufen().clear_bit());
+                                    }
+                                }
+                            }
+                            self.phase = Phase::Read;
+                        }
+                        None
+                    }
+                    Phase::Write => {
+                        let write = transaction.write;
+                        if self.index < write.len() {
+                            if sr1.tx_e().bit_is_set() {
+                                let byte = write[self.index];
+                                i2c.dr.write(|w| unsafe { w.bits(u32::from(byte)) });
+                                self.index += 1;
+                                if self.index == write.len() {
+                                    // Wait for BTF before ending the write
+                                    i2c.cr2.modify(|_, w| w.itbufen().clear_bit());
+                                }
+                            }
+                            None
+                        } else if sr1.btf().bit_is_set() {
+                            self.end_write()
+                        } else {
+                            None
+                        }
+                    }
+                    Phase::Read => {
+                        let read = &mut transaction.read;
+                        match read.len() - self.index {
+                            1 if sr1.rx_ne().bit_is_set() => {
+                                read[self.index] = i2c.dr.read().bits() as u8;
+                                self.finish(Ok(()))
+                            }
+                            2 if sr1.btf().bit_is_set() => {
+                                // The last byte waits in the shift register
+                                // while the bus is stretched
+                                i2c.cr1.modify(|_, w| w.stop().set_bit());
+                                read[self.index] = i2c.dr.read().bits() as u8;
+                                read[self.index + 1] = i2c.dr.read().bits() as u8;
+                                self.finish(Ok(()))
+                            }
+                            3 if sr1.btf().bit_is_set() => {
+                                // NACK the last byte, which is received once
+                                // the last but two is read
+                                i2c.cr1.modify(|_, w| w.ack().clear_bit());
+                                read[self.index] = i2c.dr.read().bits() as u8;
+                                self.index += 1;
+                                None
+                            }
+                            left if left > 3 && sr1.rx_ne().bit_is_set() => {
+                                read[self.index] = i2c.dr.read().bits() as u8;
+                                self.index += 1;
+                                if left == 4 {
+                                    // The last three bytes are handled on BTF
+                                    i2c.cr2.modify(|_, w| w.itbufen().clear_bit());
+                                }
+                                None
+                            }
+                            _ => None,
+                        }
+                    }
+                    Phase::Idle | Phase::Done(_) => None,
+                }
+            }
+
+            /// Handles and clears a pending error on an error interrupt
+            ///
+            /// Returns the outcome if the error ended the transaction.
+            pub fn handle_error(&mut self) -> Option<Result<(), Error>> {
+                let nack = match self.phase {
+                    Phase::Idle | Phase::Done(_) => {
+                        self.i2c.check_errors(Error::NackData).ok();
+                        return None;
+                    }
+                    Phase::Start { .. } | Phase::Address { .. } => Error::NackAddress,
+                    Phase::Write | Phase::Read => Error::NackData,
+                };
+
+                match self.i2c.check_errors(nack) {
+                    Ok(()) => None,
+                    Err(error) => self.finish(Err(error)),
+                }
+            }
+
+            /// Stops the transaction in progress and returns it
+            ///
+            /// A STOP condition is sent if the I2C still owns the bus.
+            pub fn abort(&mut self) -> Option<Transaction> {
+                let i2c = &self.i2c.i2c;
+                i2c.cr2.modify(|_, w| {
+                    w.itevten()
+                        .clear_bit()
+                        .itbufen()
+                        .clear_bit()
+                        .iterren()
+                        .clear_bit()
+                });
+                if !matches!(self.phase, Phase::Idle | Phase::Done(_))
+                    && i2c.sr2.read().msl().bit_is_set()
+                {
+                    i2c.cr1.modify(|_, w| w.stop().set_bit());
+                }
+                i2c.cr1.modify(|_, w| w.pos().clear_bit());
+
+                self.phase = Phase::Idle;
+                self.transaction.take()
+            }
+
+            /// Aborts the transaction in progress and releases the I2C
+            pub fn release(mut self) -> I2c<$I2CX, PINS> {
+                self.abort();
+                self.i2c
+            }
+
+            /// Reads after a repeated START or ends the transaction with a
+            /// STOP condition once all bytes were written
+            fn end_write(&mut self) -> Option<Result<(), Error>> {
+                let i2c = &self.i2c.i2c;
+                let read = self
+                    .transaction
+                    .as_ref()
+                    .map_or(false, |t| !t.read.is_empty());
+
+                if read {
+                    // BTF is cleared by the START condition
+                    i2c.cr1.modify(|_, w| w.ack().set_bit().start().set_bit());
+                    i2c.cr2.modify(|_, w| w.itbufen().set_bit());
+                    self.phase = Phase::Start { read: true };
+                    self.index = 0;
+                    None
+                } else {
+                    i2c.cr1.modify(|_, w| w.stop().set_bit());
+                    self.finish(Ok(()))
+                }
+            }
+
+            /// Disables the interrupts and records the outcome
+            fn finish(&mut self, result: Result<(), Error>) -> Option<Result<(), Error>> {
+                let i2c = &self.i2c.i2c;
+                i2c.cr2.modify(|_, w| {
+                    w.itevten()
+                        .clear_bit()
+                        .itbufen()
+                        .clear_bit()
+                        .iterren()
+                        .clear_bit()
+                });
+                i2c.cr1.modify(|_, w| w.pos().clear_bit());
+
+                self.phase = Phase::Done(result);
+                Some(result)
+            }
         }
 
-        impl<PINS> Read for I2c<$I2CX, PINS> {
-            type Error = Error;
+        impl<PINS> I2cSlave<$I2CX, PINS> {
+            pub fn $i2cx(
+                i2c: $I2CX,
+                pins: PINS,
+                config: SlaveConfig,
+                rcc: &mut Rcc,
+            ) -> Result<Self, InvalidConfig>
+            where
+                PINS: Pins<$I2CX>,
+            {
+                // The bus frequency is needed for data setup time in slave mode
+                let freq = rcc.clocks.apb1_clk().0 / 1_000_000;
+                if !(2..=32).contains(&freq) {
+                    return Err(InvalidConfig::BusClock);
+                }
 
-            fn read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
-                if let Some((last, buffer)) = buffer.split_last_mut() {
-                    // Send a START condition and set ACK bit
-                    self.i2c
-                        .cr1
-                        .modify(|_, w| w.start().set_bit().ack().set_bit());
-
-                    // Wait until START condition was generated
-                    while {
-                        let sr1 = self.i2c.sr1.read();
-                        sr1.sb().bit_is_clear()
-                    } {}
-
-                    // Also wait until signalled we're master and everything is waiting for us
-                    while {
-                        let sr2 = self.i2c.sr2.read();
-                        sr2.msl().bit_is_clear() && sr2.busy().bit_is_clear()
-                    } {}
-
-                    // Set up current address, we're trying to talk to
-                    self.i2c
-                        .dr
-                        .write(|w| unsafe { w.bits((u32::from(addr) << 1) + 1) });
-
-                    // Wait until address was sent
-                    while {
-                        let sr1 = self.i2c.sr1.read();
-                        sr1.addr().bit_is_clear()
-                    } {}
-
-                    // Clear condition by reading SR2
-                    self.i2c.sr2.read();
-
-                    // Receive bytes into buffer
-                    for c in buffer {
-                        *c = self.recv_byte()?;
+                pins.setup();
+
+                // Enable clock for I2C
+                rcc.rb.apb1enr.modify(|_, w| w.$i2cxen().set_bit());
+
+                // Reset I2C
+                rcc.rb.apb1rstr.modify(|_, w| w.$i2crst().set_bit());
+                rcc.rb.apb1rstr.modify(|_, w| w.$i2crst().clear_bit());
+
+                // Make sure the I2C unit is disabled so we can configure it
+                i2c.cr1.modify(|_, w| w.pe().clear_bit());
+
+                i2c.cr2.write(|w| unsafe { w.freq().bits(freq as u8) });
+
+                // Bit 14 of OAR1 must be kept at 1 by software
+                i2c.oar1.write(|w| unsafe {
+                    let w = w.bits(1 << 14);
+                    match config.address {
+                        OwnAddress::SevenBit(addr) => {
+                            w.addmode().clear_bit().add().bits(u16::from(addr) << 1)
+                        }
+                        OwnAddress::TenBit(addr) => w.addmode().set_bit().add().bits(addr),
                     }
+                });
 
-                    // Prepare to send NACK then STOP after next byte
-                    self.i2c
-                        .cr1
-                        .modify(|_, w| w.ack().clear_bit().stop().set_bit());
+                match (config.address, config.secondary_address) {
+                    (OwnAddress::SevenBit(_), Some(addr)) => {
+                        i2c.oar2.write(|w| w.add2().bits(addr).endual().set_bit())
+                    }
+                    _ => i2c.oar2.reset(),
+                }
 
-                    // Receive last byte
-                    *last = self.recv_byte()?;
+                // Enable the I2C processing
+                i2c.cr1
+                    .write(|w| w.engc().bit(config.general_call).pe().set_bit());
+                // Acknowledge own addresses, ACK is cleared while PE is 0
+                i2c.cr1.modify(|_, w| w.ack().set_bit());
 
-                    // Fallthrough is success
-                    Ok(())
+                Ok(I2cSlave { i2c, pins })
+            }
+
+            /// Starts listening for event, buffer and error interrupts
+            pub fn listen(&mut self) {
+                self.i2c.cr2.modify(|_, w| {
+                    w.itevten()
+                        .set_bit()
+                        .itbufen()
+                        .set_bit()
+                        .iterren()
+                        .set_bit()
+                });
+            }
+
+            /// Stop listening for interrupts
+            pub fn unlisten(&mut self) {
+                self.i2c.cr2.modify(|_, w| {
+                    w.itevten()
+                        .clear_bit()
+                        .itbufen()
+                        .clear_bit()
+                        .iterren()
+                        .clear_bit()
+                });
+            }
+
+            /// Handles the next pending event
+            ///
+            /// Call repeatedly until `None` is returned. A `TransmitRequest`
+            /// is repeated until a byte is provided with `send`.
+            pub fn handle_event(&mut self) -> Option<SlaveEvent> {
+                let sr1 = self.i2c.sr1.read();
+
+                if sr1.addr().bit_is_set() {
+                    // Reading SR2 after SR1 clears ADDR
+                    let sr2 = self.i2c.sr2.read();
+                    let address = if sr2.gencall().bit_is_set() {
+                        MatchedAddress::GeneralCall
+                    } else if sr2.dualf().bit_is_set() {
+                        MatchedAddress::Secondary
+                    } else {
+                        MatchedAddress::Primary
+                    };
+                    let direction = if sr2.tra().bit_is_set() {
+                        SlaveDirection::Transmit
+                    } else {
+                        SlaveDirection::Receive
+                    };
+                    Some(SlaveEvent::AddressMatch(address, direction))
+                } else if sr1.rx_ne().bit_is_set() {
+                    Some(SlaveEvent::Received(self.i2c.dr.read().bits() as u8))
+                } else if sr1.stopf().bit_is_set() {
+                    // Writing CR1 after reading SR1 clears STOPF
+                    self.i2c.cr1.modify(|_, w| w);
+                    Some(SlaveEvent::Stop)
+                } else if sr1.tx_e().bit_is_set() && self.i2c.sr2.read().tra().bit_is_set() {
+                    Some(SlaveEvent::TransmitRequest)
                 } else {
-                    Err(Error::OVERRUN)
+                    None
                 }
             }
+
+            /// Handles and clears a pending error
+            ///
+            /// A `NackData` after the last byte sent to the master is the normal
+            /// end of a slave transmission.
+            pub fn handle_error(&mut self) -> Option<Error> {
+                let sr1 = self.i2c.sr1.read();
+
+                if sr1.af().bit_is_set() {
+                    self.i2c.sr1.modify(|_, w| w.af().clear_bit());
+                    Some(Error::NackData)
+                } else if sr1.ovr().bit_is_set() {
+                    self.i2c.sr1.modify(|_, w| w.ovr().clear_bit());
+                    Some(Error::Overrun)
+                } else if sr1.berr().bit_is_set() {
+                    self.i2c.sr1.modify(|_, w| w.berr().clear_bit());
+                    Some(Error::Bus)
+                } else {
+                    None
+                }
+            }
+
+            /// Provides the next byte requested by the master
+            pub fn send(&mut self, byte: u8) {
+                self.i2c.dr.write(|w| unsafe { w.bits(u32::from(byte)) });
+            }
+
+            pub fn release(self) -> ($I2CX, PINS) {
+                (self.i2c, self.pins)
+            }
         }
 
         impl I2cExt<$I2CX> for $I2CX {
-            fn i2c<PINS, T>(self, pins: PINS, speed: T, rcc: &mut Rcc) -> I2c<$I2CX, PINS>
+            fn i2c<PINS, M>(
+                self,
+                pins: PINS,
+                mode: M,
+                rcc: &mut Rcc,
+            ) -> Result<I2c<$I2CX, PINS>, InvalidConfig>
             where
                 PINS: Pins<$I2CX>,
-                T: Into<Hertz>,
+                M: Into<Mode>,
             {
-                I2c::$i2cx(self, pins, speed.into(), rcc)
+                I2c::$i2cx(self, pins, mode, rcc)
             }
         }
     };
 }
 
 pub trait I2cExt<I2C> {
-    fn i2c<PINS, T>(self, pins: PINS, speed: T, rcc: &mut Rcc) -> I2c<I2C, PINS>
+    fn i2c<PINS, M>(
+        self,
+        pins: PINS,
+        mode: M,
+        rcc: &mut Rcc,
+    ) -> Result<I2c<I2C, PINS>, InvalidConfig>
     where
         PINS: Pins<I2C>,
-        T: Into<Hertz>;
+        M: Into<Mode>;
 }
 
 i2c!(I2C1, i2c1, i2c1en, i2c1rst);
 i2c!(I2C2, i2c2, i2c2en, i2c2rst);
+
+macro_rules! blocking {
+    ($I2CX:ident) => {
+        impl<PINS> WriteRead<SevenBitAddress> for I2c<$I2CX, PINS> {
+            type Error = Error;
+
+            fn write_read(
+                &mut self,
+                addr: SevenBitAddress,
+                bytes: &[u8],
+                buffer: &mut [u8],
+            ) -> Result<(), Self::Error> {
+                self.write_read(Address::SevenBit(addr), bytes.iter().cloned(), buffer)
+            }
+        }
+
+        impl<PINS> WriteIterRead<SevenBitAddress> for I2c<$I2CX, PINS> {
+            type Error = Error;
+
+            fn write_iter_read<B>(
+                &mut self,
+                addr: SevenBitAddress,
+                bytes: B,
+                buffer: &mut [u8],
+            ) -> Result<(), Self::Error>
+            where
+                B: IntoIterator<Item = u8>,
+            {
+                self.write_read(Address::SevenBit(addr), bytes, buffer)
+            }
+        }
+
+        impl<PINS> Write<SevenBitAddress> for I2c<$I2CX, PINS> {
+            type Error = Error;
+
+            fn write(&mut self, addr: SevenBitAddress, bytes: &[u8]) -> Result<(), Self::Error> {
+                self.write_bytes(Address::SevenBit(addr), bytes.iter().cloned())?;
+
+                // Send a STOP condition
+                self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+
+                // Fallthrough is success
+                Ok(())
+            }
+        }
+
+        impl<PINS> WriteIter<SevenBitAddress> for I2c<$I2CX, PINS> {
+            type Error = Error;
+
+            fn write<B>(&mut self, addr: SevenBitAddress, bytes: B) -> Result<(), Self::Error>
+            where
+                B: IntoIterator<Item = u8>,
+            {
+                self.write_bytes(Address::SevenBit(addr), bytes)?;
+
+                // Send a STOP condition
+                self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+
+                // Fallthrough is success
+                Ok(())
+            }
+        }
+
+        impl<PINS> Read<SevenBitAddress> for I2c<$I2CX, PINS> {
+            type Error = Error;
+
+            fn read(
+                &mut self,
+                addr: SevenBitAddress,
+                buffer: &mut [u8],
+            ) -> Result<(), Self::Error> {
+                self.read_bytes(Address::SevenBit(addr), buffer, true)
+            }
+        }
+
+        impl<PINS> Transactional<SevenBitAddress> for I2c<$I2CX, PINS> {
+            type Error = Error;
+
+            fn exec(
+                &mut self,
+                addr: SevenBitAddress,
+                operations: &mut [Operation<'_>],
+            ) -> Result<(), Self::Error> {
+                self.transaction(Address::SevenBit(addr), operations)
+            }
+        }
+    };
+}
+
+blocking!(I2C1);
+blocking!(I2C2);
@@ -1,14 +1,28 @@
+use core::cmp;
 use core::fmt;
 use core::marker::PhantomData;
 use core::ptr;
+use core::sync::atomic::{self, Ordering};
 
-use crate::gpio::gpioa::{PA10, PA2, PA3, PA9};
-use crate::gpio::gpiob::{PB10, PB11};
+use crate::bb;
+use crate::dma;
+use crate::gpio::gpioa::{PA0, PA1, PA10, PA11, PA12, PA2, PA3, PA4, PA8, PA9};
+use crate::gpio::gpiob::{PB10, PB11, PB12, PB13, PB14, PB6, PB7};
+use crate::gpio::gpioc::{PC10, PC11, PC12};
+use crate::gpio::gpiod::{PD10, PD11, PD12, PD3, PD4, PD5, PD6, PD7, PD8, PD9};
 use crate::gpio::{AltMode, Floating, Input};
 use crate::rcc::Rcc;
 use crate::stm32::{USART1, USART2, USART3};
+
+#[cfg(any(feature = "stm32l151", feature = "stm32l152", feature = "stm32l162"))]
+use crate::gpio::gpiod::PD2;
+#[cfg(any(feature = "stm32l151", feature = "stm32l152", feature = "stm32l162"))]
+use crate::stm32::{UART4, UART5};
 use hal;
+use hal::blocking::delay::DelayUs;
+use hal::digital::v2::OutputPin;
 use hal::prelude::*;
+use hal::spi::{Mode, Phase, Polarity};
 use nb::block;
 
 /// Serial error
@@ -34,9 +48,22 @@ pub enum Event {
     Txe,
     /// Idle line state detected
     Idle,
+    /// CTS input changed (USART1 to USART3 only, ignored on UART4 and UART5)
+    Cts,
+    /// LIN break detected
+    LinBreak,
+    /// Transmission complete
+    Tc,
+    /// Parity error, and framing, noise or overrun error while receiving
+    /// with DMA
+    ///
+    /// Without DMA the other receive errors are signalled through `Rxne`.
+    Error,
 }
 
 use crate::time::Bps;
+use crate::time::Hertz;
+use crate::time::MicroSeconds;
 use crate::time::U32Ext;
 
 pub enum WordLength {
@@ -61,11 +88,43 @@ pub enum StopBits {
     STOP1P5,
 }
 
+/// LIN break detection length
+pub enum LinBreakLength {
+    Bits10,
+    Bits11,
+}
+
+/// Receiver wakeup method in mute mode
+pub enum WakeUp {
+    /// Wake up on an idle line
+    IdleLine,
+    /// Wake up on a word with the MSB set and the given 4-bit address
+    AddressMark(u8),
+}
+
+/// IrDA SIR power mode
+pub enum IrdaMode {
+    Normal,
+    /// Pulses are timed from a 1.8432 MHz clock derived from the bus clock
+    LowPower,
+}
+
+/// Hardware flow control
+pub enum FlowControl {
+    None,
+    /// Deassert RTS while the receive register is full
+    Rts,
+    /// Hold back transmission while CTS is deasserted
+    Cts,
+    RtsCts,
+}
+
 pub struct Config {
     pub baudrate: Bps,
     pub wordlength: WordLength,
     pub parity: Parity,
     pub stopbits: StopBits,
+    pub flowcontrol: FlowControl,
 }
 
 impl Config {
@@ -103,10 +162,127 @@ impl Config {
         self.stopbits = stopbits;
         self
     }
+
+    pub fn flowcontrol(mut self, flowcontrol: FlowControl) -> Self {
+        self.flowcontrol = flowcontrol;
+        self
+    }
+}
+
+/// Smartcard configuration
+pub struct SmartcardConfig {
+    pub baudrate: Bps,
+    pub parity: Parity,
+    /// Frequency of the clock provided to the card
+    pub clock: Hertz,
+    /// Guard time in baud clock periods
+    pub guard_time: u8,
+    /// Signal parity errors to the card with a NACK
+    pub nack: bool,
+}
+
+impl SmartcardConfig {
+    pub fn baudrate(mut self, baudrate: Bps) -> Self {
+        self.baudrate = baudrate;
+        self
+    }
+
+    pub fn parity_even(mut self) -> Self {
+        self.parity = Parity::ParityEven;
+        self
+    }
+
+    pub fn parity_odd(mut self) -> Self {
+        self.parity = Parity::ParityOdd;
+        self
+    }
+
+    pub fn clock(mut self, clock: Hertz) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn guard_time(mut self, guard_time: u8) -> Self {
+        self.guard_time = guard_time;
+        self
+    }
+
+    pub fn nack(mut self, nack: bool) -> Self {
+        self.nack = nack;
+        self
+    }
+}
+
+impl Default for SmartcardConfig {
+    fn default() -> SmartcardConfig {
+        // 372 clock cycles per elementary time unit as defined by ISO 7816-3
+        SmartcardConfig {
+            baudrate: 9_600_u32.bps(),
+            parity: Parity::ParityEven,
+            clock: 3_571_200_u32.hz(),
+            guard_time: 0,
+            nack: true,
+        }
+    }
 }
 
+/// Reason a serial configuration was rejected
 #[derive(Debug)]
-pub struct InvalidConfig;
+pub enum InvalidConfig {
+    /// The baud rate divisor does not fit the bus clock
+    BaudRateOutOfRange,
+    /// The achievable baud rate deviates too much from the requested one
+    BaudRateTolerance,
+    /// The baud rate needs oversampling by 8, which the mode does not support
+    Oversampling,
+    /// The pins lack a signal the configuration needs
+    MissingPin,
+    /// The mode does not support the requested frame format
+    FrameFormat,
+    /// The mode does not support hardware flow control
+    FlowControl,
+    /// The clock prescaler needed by the mode is out of range
+    Prescaler,
+}
+
+/// Largest accepted deviation of the achieved baud rate, in percent
+const BAUD_TOLERANCE: u32 = 2;
+
+/// Baud rate register setting
+struct Divisor {
+    brr: u32,
+    over8: bool,
+    baudrate: Bps,
+}
+
+/// Finds the divisor closest to `baudrate`, falling back to oversampling by 8
+/// when the bus clock is too slow for oversampling by 16
+fn divisor(pclk: u32, baudrate: Bps) -> Result<Divisor, InvalidConfig> {
+    let baud = baudrate.0;
+    if baud == 0 {
+        return Err(InvalidConfig::BaudRateOutOfRange);
+    }
+
+    // Bus clock cycles per bit
+    let div = (pclk + baud / 2) / baud;
+    let (brr, over8) = match div {
+        16..=0xffff => (div, false),
+        // The fraction is 3 bits wide when oversampling by 8
+        8..=15 => ((div & !0b111) << 1 | (div & 0b111), true),
+        _ => return Err(InvalidConfig::BaudRateOutOfRange),
+    };
+
+    let achieved = (pclk + div / 2) / div;
+    if achieved.abs_diff(baud) * 100 > baud * BAUD_TOLERANCE {
+        return Err(InvalidConfig::BaudRateTolerance);
+    }
+
+    Ok(Divisor {
+        brr,
+        over8,
+        baudrate: Bps(achieved),
+    })
+}
 
 impl Default for Config {
     fn default() -> Config {
@@ -116,45 +292,284 @@ impl Default for Config {
             wordlength: WordLength::DataBits8,
             parity: Parity::ParityNone,
             stopbits: StopBits::STOP1,
+            flowcontrol: FlowControl::None,
         }
     }
 }
 
 pub trait Pins<USART> {
+    /// A Tx pin is part of the set
+    const HAS_TX: bool = true;
+    /// An Rx pin is part of the set
+    const HAS_RX: bool = true;
+    /// An RTS pin is part of the set
+    const HAS_RTS: bool = false;
+    /// A CTS pin is part of the set
+    const HAS_CTS: bool = false;
+
+    fn setup(&self);
+}
+
+pub trait PinTx<USART> {
+    const CONNECTED: bool = true;
+
+    fn setup(&self);
+}
+
+pub trait PinRx<USART> {
+    const CONNECTED: bool = true;
+
+    fn setup(&self);
+}
+
+/// A Tx pin that can carry both directions of a single-wire link
+pub trait PinHalfDuplex<USART> {
+    fn setup(&self);
+}
+
+/// A synchronous clock output pin
+pub trait PinCk<USART> {
+    fn setup(&self);
+}
+
+pub trait PinRts<USART> {
+    const CONNECTED: bool = true;
+
+    fn setup(&self);
+}
+
+pub trait PinCts<USART> {
+    const CONNECTED: bool = true;
+
     fn setup(&self);
 }
 
-impl Pins<USART1> for (PA9<Input<Floating>>, PA10<Input<Floating>>) {
+impl<USART, TX, RX> Pins<USART> for (TX, RX)
+where
+    TX: PinTx<USART>,
+    RX: PinRx<USART>,
+{
+    const HAS_TX: bool = TX::CONNECTED;
+    const HAS_RX: bool = RX::CONNECTED;
+
     fn setup(&self) {
-        self.0.set_alt_mode(AltMode::USART1_3);
-        self.1.set_alt_mode(AltMode::USART1_3);
+        self.0.setup();
+        self.1.setup();
     }
 }
 
-impl Pins<USART2> for (PA2<Input<Floating>>, PA3<Input<Floating>>) {
+impl<USART, TX, RX, RTS, CTS> Pins<USART> for (TX, RX, RTS, CTS)
+where
+    TX: PinTx<USART>,
+    RX: PinRx<USART>,
+    RTS: PinRts<USART>,
+    CTS: PinCts<USART>,
+{
+    const HAS_TX: bool = TX::CONNECTED;
+    const HAS_RX: bool = RX::CONNECTED;
+    const HAS_RTS: bool = RTS::CONNECTED;
+    const HAS_CTS: bool = CTS::CONNECTED;
+
     fn setup(&self) {
-        self.0.set_alt_mode(AltMode::USART1_3);
-        self.1.set_alt_mode(AltMode::USART1_3);
+        self.0.setup();
+        self.1.setup();
+        self.2.setup();
+        self.3.setup();
     }
 }
 
-impl Pins<USART3> for (PB10<Input<Floating>>, PB11<Input<Floating>>) {
-    fn setup(&self) {
-        self.0.set_alt_mode(AltMode::USART1_3);
-        self.1.set_alt_mode(AltMode::USART1_3);
+/// A filler type for when the Tx pin is unnecessary
+pub struct NoTx;
+/// A filler type for when the Rx pin is unnecessary
+pub struct NoRx;
+
+impl<USART> PinTx<USART> for NoTx {
+    const CONNECTED: bool = false;
+
+    fn setup(&self) {}
+}
+
+impl<USART> PinRx<USART> for NoRx {
+    const CONNECTED: bool = false;
+
+    fn setup(&self) {}
+}
+
+/// A filler type for when the RTS pin is unnecessary
+pub struct NoRts;
+/// A filler type for when the CTS pin is unnecessary
+pub struct NoCts;
+
+impl<USART> PinRts<USART> for NoRts {
+    const CONNECTED: bool = false;
+
+    fn setup(&self) {}
+}
+
+impl<USART> PinCts<USART> for NoCts {
+    const CONNECTED: bool = false;
+
+    fn setup(&self) {}
+}
+
+macro_rules! pins {
+    ($($USARTX:ty: $mode:ident,
+        TX: [$($TX:ty),*] RX: [$($RX:ty),*] RTS: [$($RTS:ty),*] CTS: [$($CTS:ty),*]
+        CK: [$($CK:ty),*]
+    )+) => {
+        $(
+            $(
+                impl PinTx<$USARTX> for $TX {
+                    fn setup(&self) {
+                        self.set_alt_mode(AltMode::$mode);
+                    }
+                }
+
+                impl PinHalfDuplex<$USARTX> for $TX {
+                    fn setup(&self) {
+                        self.set_open_drain();
+                        self.set_alt_mode(AltMode::$mode);
+                    }
+                }
+            )*
+            $(
+                impl PinRx<$USARTX> for $RX {
+                    fn setup(&self) {
+                        self.set_alt_mode(AltMode::$mode);
+                    }
+                }
+            )*
+            $(
+                impl PinRts<$USARTX> for $RTS {
+                    fn setup(&self) {
+                        self.set_alt_mode(AltMode::$mode);
+                    }
+                }
+            )*
+            $(
+                impl PinCts<$USARTX> for $CTS {
+                    fn setup(&self) {
+                        self.set_alt_mode(AltMode::$mode);
+                    }
+                }
+            )*
+            $(
+                impl PinCk<$USARTX> for $CK {
+                    fn setup(&self) {
+                        self.set_alt_mode(AltMode::$mode);
+                    }
+                }
+            )*
+        )+
     }
 }
 
+pins! {
+    USART1: USART1_3,
+        TX: [
+            PA9<Input<Floating>>,
+            PB6<Input<Floating>>
+        ]
+        RX: [
+            PA10<Input<Floating>>,
+            PB7<Input<Floating>>
+        ]
+        RTS: [
+            PA12<Input<Floating>>
+        ]
+        CTS: [
+            PA11<Input<Floating>>
+        ]
+        CK: [
+            PA8<Input<Floating>>
+        ]
+
+    USART2: USART1_3,
+        TX: [
+            PA2<Input<Floating>>,
+            PD5<Input<Floating>>
+        ]
+        RX: [
+            PA3<Input<Floating>>,
+            PD6<Input<Floating>>
+        ]
+        RTS: [
+            PA1<Input<Floating>>,
+            PD4<Input<Floating>>
+        ]
+        CTS: [
+            PA0<Input<Floating>>,
+            PD3<Input<Floating>>
+        ]
+        CK: [
+            PA4<Input<Floating>>,
+            PD7<Input<Floating>>
+        ]
+
+    USART3: USART1_3,
+        TX: [
+            PB10<Input<Floating>>,
+            PC10<Input<Floating>>,
+            PD8<Input<Floating>>
+        ]
+        RX: [
+            PB11<Input<Floating>>,
+            PC11<Input<Floating>>,
+            PD9<Input<Floating>>
+        ]
+        RTS: [
+            PB14<Input<Floating>>,
+            PD12<Input<Floating>>
+        ]
+        CTS: [
+            PB13<Input<Floating>>,
+            PD11<Input<Floating>>
+        ]
+        CK: [
+            PB12<Input<Floating>>,
+            PC12<Input<Floating>>,
+            PD10<Input<Floating>>
+        ]
+}
+
+#[cfg(any(feature = "stm32l151", feature = "stm32l152", feature = "stm32l162"))]
+pins! {
+    UART4: UART4_5,
+        TX: [
+            PC10<Input<Floating>>
+        ]
+        RX: [
+            PC11<Input<Floating>>
+        ]
+        RTS: []
+        CTS: []
+        CK: []
+
+    UART5: UART4_5,
+        TX: [
+            PC12<Input<Floating>>
+        ]
+        RX: [
+            PD2<Input<Floating>>
+        ]
+        RTS: []
+        CTS: []
+        CK: []
+}
+
 /// Serial abstraction
 pub struct Serial<USART> {
     usart: USART,
     rx: Rx<USART>,
     tx: Tx<USART>,
+    baudrate: Bps,
 }
 
 /// Serial receiver
 pub struct Rx<USART> {
     _usart: PhantomData<USART>,
+    /// Valid word received along with a noise or overrun error
+    pending: Option<u16>,
 }
 
 /// Serial transmitter
@@ -162,6 +577,251 @@ pub struct Tx<USART> {
     _usart: PhantomData<USART>,
 }
 
+impl<USART> fmt::Debug for Rx<USART> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Rx").finish()
+    }
+}
+
+impl<USART> fmt::Debug for Tx<USART> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Tx").finish()
+    }
+}
+
+/// Single-wire half-duplex serial abstraction
+///
+/// The transmitter and receiver share the Tx pin. The receiver is switched
+/// off while writing and only switched back on once the last byte has left
+/// the shift register, so the own transmission is never read back.
+pub struct HalfDuplex<USART> {
+    serial: Serial<USART>,
+}
+
+/// Synchronous serial abstraction
+///
+/// The USART drives a clock on the CK pin and works as an SPI master. Data
+/// is shifted out least significant bit first.
+pub struct SyncSerial<USART> {
+    serial: Serial<USART>,
+}
+
+/// IrDA SIR serial abstraction
+pub struct Irda<USART> {
+    serial: Serial<USART>,
+}
+
+/// ISO 7816 smartcard abstraction
+///
+/// Data is exchanged over a single open-drain line like in [`HalfDuplex`],
+/// the card is clocked from the CK pin.
+pub struct Smartcard<USART> {
+    serial: Serial<USART>,
+}
+
+/// DMA transfer from memory to a serial transmitter
+pub struct TxTransfer<USART, CHANNEL, BUFFER> {
+    tx: Tx<USART>,
+    transfer: dma::Transfer<CHANNEL, BUFFER>,
+}
+
+/// DMA transfer from a serial receiver to memory
+pub struct RxTransfer<USART, CHANNEL, BUFFER> {
+    rx: Rx<USART>,
+    transfer: dma::Transfer<CHANNEL, BUFFER>,
+}
+
+/// Serial receiver continuously writing into a ring buffer using circular DMA
+///
+/// The application has to consume the received bytes before the DMA engine
+/// wraps around and reaches them again, otherwise `Error::Overrun` is reported
+/// and the unread bytes are dropped.
+pub struct RxRing<USART, CHANNEL, const N: usize> {
+    rx: Rx<USART>,
+    channel: CHANNEL,
+    buffer: &'static mut [u8; N],
+    read: usize,
+    write: usize,
+}
+
+impl<USART, CHANNEL, const N: usize> RxRing<USART, CHANNEL, N>
+where
+    CHANNEL: dma::Channel,
+{
+    /// Returns the number of received bytes that have not been read yet
+    pub fn available(&mut self) -> Result<usize, Error> {
+        self.update()
+    }
+
+    /// Moves as many received bytes as fit into `buf` and returns their count
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let count = cmp::min(self.update()?, buf.len());
+        for (i, byte) in buf[..count].iter_mut().enumerate() {
+            *byte = self.buffer[(self.read + i) % N];
+        }
+        self.read = (self.read + count) % N;
+        Ok(count)
+    }
+
+    /// Hands all received bytes to `f` and consumes them
+    fn drain<R, F>(&mut self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&[u8], &[u8]) -> R,
+    {
+        let pending = self.update()?;
+        let end = self.read + pending;
+        let ret = if end <= N {
+            f(&self.buffer[self.read..end], &[])
+        } else {
+            f(&self.buffer[self.read..], &self.buffer[..end - N])
+        };
+        self.read = end % N;
+        Ok(ret)
+    }
+
+    fn update(&mut self) -> Result<usize, Error> {
+        let wrapped = self.channel.is_event(dma::Event::TransferComplete);
+        self.channel.clear_event(dma::Event::TransferComplete);
+
+        let write = (N - usize::from(self.channel.remaining())) % N;
+        if write < self.write {
+            // the wrap we just observed may have set the flag again
+            self.channel.clear_event(dma::Event::TransferComplete);
+        }
+        // the engine went all the way around since the last update
+        let lapped = wrapped && write >= self.write;
+
+        let pending = (self.write + N - self.read) % N;
+        let progress = (write + N - self.write) % N;
+        self.write = write;
+        atomic::compiler_fence(Ordering::Acquire);
+
+        if lapped || pending + progress >= N {
+            self.read = write;
+            Err(Error::Overrun)
+        } else {
+            Ok(pending + progress)
+        }
+    }
+}
+
+/// RS-485 transmitter with a driver enable output
+///
+/// The `de` pin is driven high before the first byte is sent and low again
+/// once the transmission is complete, either from `flush` or from
+/// `handle_tc` in the USART interrupt handler. Errors reported by `de` are
+/// ignored.
+pub struct Rs485<USART, DE, DELAY> {
+    tx: Tx<USART>,
+    de: DE,
+    delay: DELAY,
+    setup: MicroSeconds,
+    hold: MicroSeconds,
+    active: bool,
+}
+
+impl<USART, DE, DELAY> Rs485<USART, DE, DELAY>
+where
+    DE: OutputPin,
+    DELAY: DelayUs<u32>,
+{
+    /// Sets the time the driver is enabled before the first byte and kept
+    /// enabled after the last one
+    pub fn turnaround(mut self, setup: MicroSeconds, hold: MicroSeconds) -> Self {
+        self.setup = setup;
+        self.hold = hold;
+        self
+    }
+
+    /// Returns `true` while the bus driver is enabled
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    fn assert_de(&mut self) {
+        if !self.active {
+            self.de.set_high().ok();
+            self.delay.delay_us(self.setup.0);
+            self.active = true;
+        }
+    }
+
+    fn release_de(&mut self) {
+        if self.active {
+            self.delay.delay_us(self.hold.0);
+            self.de.set_low().ok();
+            self.active = false;
+        }
+    }
+}
+
+/// Interrupt driven serial with fixed capacity transmit and receive queues
+///
+/// Call `handle_interrupt` from the USART interrupt handler.
+pub struct BufferedSerial<USART, const TX: usize, const RX: usize> {
+    serial: Serial<USART>,
+    tx: Queue<TX>,
+    rx: Queue<RX>,
+    overflows: u32,
+}
+
+impl<USART, const TX: usize, const RX: usize> BufferedSerial<USART, TX, RX> {
+    /// Returns the number of received bytes that were dropped, either because
+    /// the receive queue was full or because the USART overran
+    pub fn overflows(&self) -> u32 {
+        self.overflows
+    }
+
+    /// Returns the number of received bytes waiting in the receive queue
+    pub fn available(&self) -> usize {
+        self.rx.len()
+    }
+}
+
+/// Fixed capacity byte FIFO
+struct Queue<const N: usize> {
+    buffer: [u8; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> Queue<N> {
+    const fn new() -> Self {
+        Queue {
+            buffer: [0; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn enqueue(&mut self, byte: u8) -> Result<(), u8> {
+        if self.len == N {
+            return Err(byte);
+        }
+        self.buffer[(self.head + self.len) % N] = byte;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn dequeue(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.buffer[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
 pub trait SerialExt<USART, PINS> {
     fn usart(
         self,
@@ -173,7 +833,7 @@ pub trait SerialExt<USART, PINS> {
 
 macro_rules! usart {
     ($(
-        $USARTX:ident: ($usartX:ident, $apbXenr:ident, $usartXen:ident, $pclkX:ident, $SerialExt:ident),
+        $USARTX:ident: ($usartX:ident, $apbXenr:ident, $usartXen:ident, $pclkX:ident, $SerialExt:ident, $cts:expr),
     )+) => {
         $(
             impl<PINS> SerialExt<$USARTX, PINS> for $USARTX
@@ -195,32 +855,40 @@ macro_rules! usart {
                 where
                     PINS: Pins<$USARTX>,
                 {
+                    let (rtse, ctse) = match config.flowcontrol {
+                        FlowControl::None => (false, false),
+                        FlowControl::Rts => (true, false),
+                        FlowControl::Cts => (false, true),
+                        FlowControl::RtsCts => (true, true),
+                    };
+                    if (rtse && !PINS::HAS_RTS) || (ctse && !PINS::HAS_CTS) {
+                        return Err(InvalidConfig::MissingPin);
+                    }
+
+                    let divisor = divisor(rcc.clocks.$pclkX().0, config.baudrate)?;
+
                     pins.setup();
 
                     // Enable clock for USART
                     rcc.rb.$apbXenr.modify(|_, w| w.$usartXen().set_bit());
 
-                    // Calculate correct baudrate divisor on the fly
-                    let div = (rcc.clocks.$pclkX().0 * 25) / (4 * config.baudrate.0);
-                    let mantissa = div / 100;
-                    let fraction = ((div - mantissa * 100) * 16 + 50) / 100;
-                    usart
-                        .brr
-                        .write(|w| unsafe { w.bits(mantissa << 4 | fraction) });
+                    usart.brr.write(|w| unsafe { w.bits(divisor.brr) });
 
                     // Reset other registers to disable advanced USART features
                     usart.cr2.reset();
                     usart.cr3.reset();
 
-                    // Enable transmission and receiving
+                    // Enable transmission and receiving for the connected pins
                     // and configure frame
                     usart.cr1.write(|w| {
                         w.ue()
                             .set_bit()
+                            .over8()
+                            .bit(divisor.over8)
                             .te()
-                            .set_bit()
+                            .bit(PINS::HAS_TX)
                             .re()
-                            .set_bit()
+                            .bit(PINS::HAS_RX)
                             .m()
                             .bit(match config.wordlength {
                                 WordLength::DataBits8 => false,
@@ -244,10 +912,16 @@ macro_rules! usart {
                             StopBits::STOP1P5 => 0b11,
                         })
                     });
+
+                    usart.cr3.write(|w| w.rtse().bit(rtse).ctse().bit(ctse));
                     Ok(Serial {
                         usart,
                         tx: Tx { _usart: PhantomData },
-                        rx: Rx { _usart: PhantomData },
+                        rx: Rx {
+                            _usart: PhantomData,
+                            pending: None,
+                        },
+                        baudrate: divisor.baudrate,
                     })
                 }
 
@@ -263,6 +937,20 @@ macro_rules! usart {
                         Event::Idle => {
                             self.usart.cr1.modify(|_, w| w.idleie().set_bit())
                         },
+                        // CTSIE is reserved on the UARTs
+                        Event::Cts => if $cts {
+                            self.usart.cr3.modify(|_, w| w.ctsie().set_bit())
+                        },
+                        Event::LinBreak => {
+                            self.usart.cr2.modify(|_, w| w.lbdie().set_bit())
+                        },
+                        Event::Tc => {
+                            self.usart.cr1.modify(|_, w| w.tcie().set_bit())
+                        },
+                        Event::Error => {
+                            self.usart.cr1.modify(|_, w| w.peie().set_bit());
+                            self.usart.cr3.modify(|_, w| w.eie().set_bit())
+                        },
                     }
                 }
 
@@ -278,66 +966,954 @@ macro_rules! usart {
                         Event::Idle => {
                             self.usart.cr1.modify(|_, w| w.idleie().clear_bit())
                         },
+                        Event::Cts => if $cts {
+                            self.usart.cr3.modify(|_, w| w.ctsie().clear_bit())
+                        },
+                        Event::LinBreak => {
+                            self.usart.cr2.modify(|_, w| w.lbdie().clear_bit())
+                        },
+                        Event::Tc => {
+                            self.usart.cr1.modify(|_, w| w.tcie().clear_bit())
+                        },
+                        Event::Error => {
+                            self.usart.cr1.modify(|_, w| w.peie().clear_bit());
+                            self.usart.cr3.modify(|_, w| w.eie().clear_bit())
+                        },
                     }
                 }
 
                 /// Clears interrupt flag
                 pub fn clear_irq(&mut self, event: Event) {
-                    if let Event::Rxne = event {
-                       self.usart.sr.modify(|_, w| w.rxne().clear_bit())
+                    match event {
+                        Event::Rxne => {
+                            self.usart.sr.modify(|_, w| w.rxne().clear_bit())
+                        },
+                        Event::Cts => if $cts {
+                            self.usart.sr.modify(|_, w| w.cts().clear_bit())
+                        },
+                        Event::LinBreak => {
+                            self.usart.sr.modify(|_, w| w.lbd().clear_bit())
+                        },
+                        Event::Tc => {
+                            self.usart.sr.modify(|_, w| w.tc().clear_bit())
+                        },
+                        // Cleared by reading SR followed by DR. A pending word
+                        // is left for `read`, which clears the flags as well
+                        Event::Idle | Event::Error
+                            if self.usart.sr.read().rxne().bit_is_clear() =>
+                        {
+                            self.usart.dr.read();
+                        },
+                        _ => {},
                     }
                 }
 
-                pub fn split(self) -> (Tx<$USARTX>, Rx<$USARTX>) {
-                    (self.tx, self.rx)
+                /// Returns the baud rate achieved with the bus clock
+                pub fn baudrate(&self) -> Bps {
+                    self.baudrate
                 }
 
-                pub fn release(self) -> $USARTX {
-                    self.usart
+                /// Enables LIN mode with the given break detection length
+                ///
+                /// LIN mode requires a single stop bit and cannot be combined
+                /// with the synchronous, smartcard, IrDA or half-duplex modes.
+                pub fn enable_lin(&mut self, length: LinBreakLength) -> Result<(), InvalidConfig> {
+                    if self.usart.cr1.read().over8().bit_is_set() {
+                        return Err(InvalidConfig::Oversampling);
+                    }
+                    if self.usart.cr2.read().stop().bits() != 0 {
+                        return Err(InvalidConfig::FrameFormat);
+                    }
+
+                    // LINEN must not change while the USART is enabled
+                    self.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    self.usart.cr2.modify(|_, w| {
+                        w.linen().set_bit().lbdl().bit(match length {
+                            LinBreakLength::Bits10 => false,
+                            LinBreakLength::Bits11 => true,
+                        })
+                    });
+                    self.usart.cr1.modify(|_, w| w.ue().set_bit());
+                    Ok(())
                 }
-            }
 
-            impl hal::serial::Read<u8> for Serial<$USARTX> {
-                type Error = Error;
+                pub fn disable_lin(&mut self) {
+                    self.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    self.usart.cr2.modify(|_, w| w.linen().clear_bit());
+                    self.usart.cr1.modify(|_, w| w.ue().set_bit());
+                }
 
-                fn read(&mut self) -> nb::Result<u8, Error> {
-                    self.rx.read()
+                /// Sends a break after the current transmission
+                ///
+                /// In LIN mode the break is 13 bits long.
+                pub fn send_break(&mut self) {
+                    self.tx.send_break()
                 }
-            }
 
-             impl hal::serial::Write<u8> for  Serial<$USARTX> {
-                type Error = Error;
+                /// Selects how the receiver leaves mute mode
+                pub fn set_wakeup(&mut self, wakeup: WakeUp) {
+                    match wakeup {
+                        WakeUp::IdleLine => self.usart.cr1.modify(|_, w| w.wake().clear_bit()),
+                        WakeUp::AddressMark(address) => {
+                            self.usart
+                                .cr2
+                                .modify(|_, w| unsafe { w.add().bits(address & 0x0f) });
+                            self.usart.cr1.modify(|_, w| w.wake().set_bit());
+                        }
+                    }
+                }
 
-                fn flush(&mut self) -> nb::Result<(), Self::Error> {
-                    self.tx.flush()
+                /// Puts the receiver into mute mode until the wakeup condition occurs
+                pub fn mute(&mut self) {
+                    self.usart.cr1.modify(|_, w| w.rwu().set_bit());
                 }
 
-                fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
-                    self.tx.write(byte)
+                pub fn unmute(&mut self) {
+                    self.usart.cr1.modify(|_, w| w.rwu().clear_bit());
                 }
-            }
 
-            impl hal::serial::Read<u8> for Rx<$USARTX> {
-                type Error = Error;
+                pub fn is_muted(&self) -> bool {
+                    self.usart.cr1.read().rwu().bit_is_set()
+                }
 
-                fn read(&mut self) -> nb::Result<u8, Error> {
-                    // NOTE(unsafe) atomic read with no side effects
+                pub fn split(self) -> (Tx<$USARTX>, Rx<$USARTX>) {
+                    (self.tx, self.rx)
+                }
+
+                /// Reads from a line shared with the transmitter
+                fn read_single_wire(&mut self) -> nb::Result<u8, Error> {
+                    if self.usart.cr1.read().re().bit_is_clear() {
+                        // Wait for the transmission to finish before the
+                        // line is given back to the receiver
+                        if self.usart.sr.read().tc().bit_is_clear() {
+                            return Err(nb::Error::WouldBlock);
+                        }
+                        self.usart.cr1.modify(|_, w| w.re().set_bit());
+                    }
+                    self.rx.read()
+                }
+
+                /// Writes to a line shared with the receiver
+                fn write_single_wire(&mut self, byte: u8) -> nb::Result<(), Error> {
+                    self.usart.cr1.modify(|_, w| w.re().clear_bit());
+                    self.tx.write(byte)
+                }
+
+                pub fn release(self) -> $USARTX {
+                    self.usart
+                }
+            }
+
+            impl HalfDuplex<$USARTX> {
+                /// Configures the USART for single-wire half-duplex operation on `pin`
+                ///
+                /// The pin is switched to open drain, an external pull-up is
+                /// required. Hardware flow control cannot be used.
+                pub fn $usartX<PIN>(
+                    usart: $USARTX,
+                    pin: PIN,
+                    config: Config,
+                    rcc: &mut Rcc,
+                ) -> Result<Self, InvalidConfig>
+                where
+                    PIN: PinHalfDuplex<$USARTX>,
+                {
+                    if !matches!(config.flowcontrol, FlowControl::None) {
+                        return Err(InvalidConfig::FlowControl);
+                    }
+
+                    let serial = Serial::$usartX(usart, (NoTx, NoRx), config, rcc)?;
+                    pin.setup();
+
+                    // HDSEL must not change while the USART is enabled
+                    serial.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    serial.usart.cr3.modify(|_, w| w.hdsel().set_bit());
+                    // The single pin both transmits and receives
+                    serial
+                        .usart
+                        .cr1
+                        .modify(|_, w| w.te().set_bit().re().set_bit().ue().set_bit());
+
+                    Ok(HalfDuplex { serial })
+                }
+
+                /// Starts listening for an interrupt event
+                pub fn listen(&mut self, event: Event) {
+                    self.serial.listen(event)
+                }
+
+                /// Stop listening for an interrupt event
+                pub fn unlisten(&mut self, event: Event) {
+                    self.serial.unlisten(event)
+                }
+
+                pub fn release(self) -> $USARTX {
+                    self.serial.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    self.serial.usart.cr3.modify(|_, w| w.hdsel().clear_bit());
+                    self.serial.release()
+                }
+            }
+
+            impl hal::serial::Read<u8> for HalfDuplex<$USARTX> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u8, Error> {
+                    self.serial.read_single_wire()
+                }
+            }
+
+            impl hal::serial::Write<u8> for HalfDuplex<$USARTX> {
+                type Error = Error;
+
+                fn flush(&mut self) -> nb::Result<(), Self::Error> {
+                    self.serial.tx.flush()
+                }
+
+                fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+                    self.serial.write_single_wire(byte)
+                }
+            }
+
+            impl Irda<$USARTX> {
+                /// Configures the USART for IrDA SIR operation
+                ///
+                /// Only one stop bit is allowed and hardware flow control
+                /// cannot be used.
+                pub fn $usartX<PINS>(
+                    usart: $USARTX,
+                    pins: PINS,
+                    config: Config,
+                    mode: IrdaMode,
+                    rcc: &mut Rcc,
+                ) -> Result<Self, InvalidConfig>
+                where
+                    PINS: Pins<$USARTX>,
+                {
+                    if !matches!(config.stopbits, StopBits::STOP1) {
+                        return Err(InvalidConfig::FrameFormat);
+                    }
+                    if !matches!(config.flowcontrol, FlowControl::None) {
+                        return Err(InvalidConfig::FlowControl);
+                    }
+                    if divisor(rcc.clocks.$pclkX().0, config.baudrate)?.over8 {
+                        return Err(InvalidConfig::Oversampling);
+                    }
+
+                    let psc = match mode {
+                        IrdaMode::Normal => 1,
+                        IrdaMode::LowPower => {
+                            (rcc.clocks.$pclkX().0 + 921_600) / 1_843_200
+                        }
+                    };
+                    if psc == 0 || psc > 0xff {
+                        return Err(InvalidConfig::Prescaler);
+                    }
+
+                    let serial = Serial::$usartX(usart, pins, config, rcc)?;
+
+                    // IREN and IRLP must not change while the USART is enabled
+                    serial.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    serial
+                        .usart
+                        .gtpr
+                        .modify(|_, w| unsafe { w.psc().bits(psc as u8) });
+                    serial.usart.cr3.modify(|_, w| {
+                        w.iren()
+                            .set_bit()
+                            .irlp()
+                            .bit(matches!(mode, IrdaMode::LowPower))
+                    });
+                    serial.usart.cr1.modify(|_, w| w.ue().set_bit());
+
+                    Ok(Irda { serial })
+                }
+
+                /// Starts listening for an interrupt event
+                pub fn listen(&mut self, event: Event) {
+                    self.serial.listen(event)
+                }
+
+                /// Stop listening for an interrupt event
+                pub fn unlisten(&mut self, event: Event) {
+                    self.serial.unlisten(event)
+                }
+
+                pub fn release(self) -> $USARTX {
+                    self.serial.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    self.serial
+                        .usart
+                        .cr3
+                        .modify(|_, w| w.iren().clear_bit().irlp().clear_bit());
+                    self.serial.release()
+                }
+            }
+
+            impl hal::serial::Read<u8> for Irda<$USARTX> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u8, Error> {
+                    self.serial.rx.read()
+                }
+            }
+
+            impl hal::serial::Write<u8> for Irda<$USARTX> {
+                type Error = Error;
+
+                fn flush(&mut self) -> nb::Result<(), Self::Error> {
+                    self.serial.tx.flush()
+                }
+
+                fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+                    self.serial.tx.write(byte)
+                }
+            }
+
+            impl Smartcard<$USARTX> {
+                /// Configures the USART as a smartcard interface
+                ///
+                /// `io` is switched to open drain, an external pull-up is
+                /// required. Frames always use 8 data bits, a parity bit and
+                /// 1.5 stop bits.
+                pub fn $usartX<CK, IO>(
+                    usart: $USARTX,
+                    ck: CK,
+                    io: IO,
+                    config: SmartcardConfig,
+                    rcc: &mut Rcc,
+                ) -> Result<Self, InvalidConfig>
+                where
+                    CK: PinCk<$USARTX>,
+                    IO: PinHalfDuplex<$USARTX>,
+                {
+                    let SmartcardConfig {
+                        baudrate,
+                        parity,
+                        clock,
+                        guard_time,
+                        nack,
+                    } = config;
+                    if let Parity::ParityNone = parity {
+                        return Err(InvalidConfig::FrameFormat);
+                    }
+                    if divisor(rcc.clocks.$pclkX().0, baudrate)?.over8 {
+                        return Err(InvalidConfig::Oversampling);
+                    }
+
+                    // The card clock is the bus clock divided by 2 * PSC
+                    let pclk = rcc.clocks.$pclkX().0;
+                    let clock = cmp::max(clock.0, 1);
+                    let psc = pclk.div_ceil(2 * clock);
+                    if psc == 0 || psc > 0b1_1111 {
+                        return Err(InvalidConfig::Prescaler);
+                    }
+
+                    let frame = Config {
+                        baudrate,
+                        wordlength: WordLength::DataBits9,
+                        parity,
+                        stopbits: StopBits::STOP1P5,
+                        flowcontrol: FlowControl::None,
+                    };
+                    let serial = Serial::$usartX(usart, (NoTx, NoRx), frame, rcc)?;
+                    io.setup();
+                    ck.setup();
+
+                    // SCEN, NACK and CLKEN must not change while the USART is
+                    // enabled
+                    serial.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    serial.usart.gtpr.write(|w| unsafe {
+                        w.gt().bits(guard_time).psc().bits(psc as u8)
+                    });
+                    serial.usart.cr2.modify(|_, w| w.clken().set_bit());
+                    serial
+                        .usart
+                        .cr3
+                        .modify(|_, w| w.scen().set_bit().nack().bit(nack));
+                    // The IO pin both transmits and receives
+                    serial
+                        .usart
+                        .cr1
+                        .modify(|_, w| w.te().set_bit().re().set_bit().ue().set_bit());
+
+                    Ok(Smartcard { serial })
+                }
+
+                /// Starts listening for an interrupt event
+                pub fn listen(&mut self, event: Event) {
+                    self.serial.listen(event)
+                }
+
+                /// Stop listening for an interrupt event
+                pub fn unlisten(&mut self, event: Event) {
+                    self.serial.unlisten(event)
+                }
+
+                pub fn release(self) -> $USARTX {
+                    self.serial.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    self.serial.usart.cr2.modify(|_, w| w.clken().clear_bit());
+                    self.serial
+                        .usart
+                        .cr3
+                        .modify(|_, w| w.scen().clear_bit().nack().clear_bit());
+                    self.serial.release()
+                }
+            }
+
+            impl hal::serial::Read<u8> for Smartcard<$USARTX> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u8, Error> {
+                    self.serial.rx.read()
+                }
+            }
+
+            /// The receiver stays enabled while writing, the transmitter
+            /// needs it to see a NACK from the card and repeat the byte
+            impl hal::serial::Write<u8> for Smartcard<$USARTX> {
+                type Error = Error;
+
+                fn flush(&mut self) -> nb::Result<(), Self::Error> {
+                    self.serial.tx.flush()
+                }
+
+                fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+                    self.serial.tx.write(byte)
+                }
+            }
+
+            impl SyncSerial<$USARTX> {
+                /// Configures the USART as a synchronous master clocking on `ck`
+                ///
+                /// A clock pulse is generated for every data bit, including
+                /// the last one. Only 8-bit words are supported.
+                pub fn $usartX<CK, PINS>(
+                    usart: $USARTX,
+                    ck: CK,
+                    pins: PINS,
+                    mode: Mode,
+                    config: Config,
+                    rcc: &mut Rcc,
+                ) -> Result<Self, InvalidConfig>
+                where
+                    CK: PinCk<$USARTX>,
+                    PINS: Pins<$USARTX>,
+                {
+                    if let WordLength::DataBits9 = config.wordlength {
+                        return Err(InvalidConfig::FrameFormat);
+                    }
+                    if !PINS::HAS_TX || !PINS::HAS_RX {
+                        return Err(InvalidConfig::MissingPin);
+                    }
+
+                    let serial = Serial::$usartX(usart, pins, config, rcc)?;
+                    ck.setup();
+
+                    // CLKEN, CPOL, CPHA and LBCL must not change while the
+                    // USART is enabled
+                    serial.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    serial.usart.cr2.modify(|_, w| {
+                        w.clken()
+                            .set_bit()
+                            .cpol()
+                            .bit(mode.polarity == Polarity::IdleHigh)
+                            .cpha()
+                            .bit(mode.phase == Phase::CaptureOnSecondTransition)
+                            .lbcl()
+                            .set_bit()
+                    });
+                    serial.usart.cr1.modify(|_, w| w.ue().set_bit());
+
+                    Ok(SyncSerial { serial })
+                }
+
+                pub fn release(self) -> $USARTX {
+                    self.serial.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    self.serial.usart.cr2.modify(|_, w| w.clken().clear_bit());
+                    self.serial.release()
+                }
+            }
+
+            impl hal::spi::FullDuplex<u8> for SyncSerial<$USARTX> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u8, Error> {
+                    self.serial.rx.read()
+                }
+
+                fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
+                    self.serial.tx.write(byte)
+                }
+            }
+
+            impl hal::blocking::spi::transfer::Default<u8> for SyncSerial<$USARTX> {}
+
+            impl hal::blocking::spi::write::Default<u8> for SyncSerial<$USARTX> {}
+
+            impl<const TX: usize, const RX: usize> BufferedSerial<$USARTX, TX, RX> {
+                /// Wraps `serial` and starts listening for received bytes
+                pub fn new(mut serial: Serial<$USARTX>) -> Self {
+                    serial.listen(Event::Rxne);
+                    BufferedSerial {
+                        serial,
+                        tx: Queue::new(),
+                        rx: Queue::new(),
+                        overflows: 0,
+                    }
+                }
+
+                /// Moves the received bytes into the receive queue
+                pub fn handle_rxne(&mut self) {
+                    loop {
+                        match self.serial.rx.read() {
+                            Ok(byte) => {
+                                if self.rx.enqueue(byte).is_err() {
+                                    self.overflows = self.overflows.wrapping_add(1);
+                                }
+                            }
+                            Err(nb::Error::WouldBlock) => break,
+                            Err(nb::Error::Other(Error::Overrun)) => {
+                                self.overflows = self.overflows.wrapping_add(1);
+                            }
+                            Err(nb::Error::Other(_)) => {}
+                        }
+                    }
+                }
+
+                /// Moves the next byte of the transmit queue to the USART
+                pub fn handle_txe(&mut self) {
+                    // NOTE(unsafe) atomic read with no side effects
+                    if unsafe { (*$USARTX::ptr()).sr.read().txe().bit_is_clear() } {
+                        return;
+                    }
+
+                    match self.tx.dequeue() {
+                        Some(byte) => {
+                            self.serial.tx.write(byte).ok();
+                        }
+                        None => self.serial.unlisten(Event::Txe),
+                    }
+                }
+
+                /// Services both queues, call this from the USART interrupt handler
+                pub fn handle_interrupt(&mut self) {
+                    self.handle_rxne();
+                    self.handle_txe();
+                }
+
+                /// Stops listening for interrupts and releases the serial
+                ///
+                /// Bytes still in the queues are dropped.
+                pub fn release(mut self) -> Serial<$USARTX> {
+                    self.serial.unlisten(Event::Rxne);
+                    self.serial.unlisten(Event::Txe);
+                    self.serial
+                }
+            }
+
+            impl<const TX: usize, const RX: usize> hal::serial::Read<u8>
+                for BufferedSerial<$USARTX, TX, RX>
+            {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u8, Error> {
+                    self.rx.dequeue().ok_or(nb::Error::WouldBlock)
+                }
+            }
+
+            impl<const TX: usize, const RX: usize> hal::serial::Write<u8>
+                for BufferedSerial<$USARTX, TX, RX>
+            {
+                type Error = Error;
+
+                fn flush(&mut self) -> nb::Result<(), Self::Error> {
+                    if self.tx.is_empty() {
+                        self.serial.tx.flush()
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+                    self.tx
+                        .enqueue(byte)
+                        .map_err(|_| nb::Error::WouldBlock)?;
+                    self.serial.listen(Event::Txe);
+                    Ok(())
+                }
+            }
+
+            impl hal::serial::Read<u8> for Serial<$USARTX> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u8, Error> {
+                    self.rx.read()
+                }
+            }
+
+             impl hal::serial::Write<u8> for  Serial<$USARTX> {
+                type Error = Error;
+
+                fn flush(&mut self) -> nb::Result<(), Self::Error> {
+                    self.tx.flush()
+                }
+
+                fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+                    self.tx.write(byte)
+                }
+            }
+
+            impl Serial<$USARTX> {
+                /// Reads a 9-bit word when `WordLength::DataBits9` is selected
+                pub fn read_u16(&mut self) -> nb::Result<u16, Error> {
+                    self.rx.read_u16()
+                }
+
+                /// Writes a 9-bit word when `WordLength::DataBits9` is selected
+                pub fn write_u16(&mut self, word: u16) -> nb::Result<(), Error> {
+                    self.tx.write_u16(word)
+                }
+            }
+
+            impl<DE, DELAY> Rs485<$USARTX, DE, DELAY>
+            where
+                DE: OutputPin,
+                DELAY: DelayUs<u32>,
+            {
+                /// Wraps `tx` with the driver enable pin `de` released
+                pub fn new(tx: Tx<$USARTX>, mut de: DE, delay: DELAY) -> Self {
+                    de.set_low().ok();
+                    Rs485 {
+                        tx,
+                        de,
+                        delay,
+                        setup: MicroSeconds(0),
+                        hold: MicroSeconds(0),
+                        active: false,
+                    }
+                }
+
+                /// Starts listening for the transmission complete interrupt
+                pub fn listen(&mut self) {
+                    // NOTE(unsafe) CR1 is not modified by the receiver half after
+                    // `split`, so this read-modify-write cannot race
+                    unsafe { (*$USARTX::ptr()).cr1.modify(|_, w| w.tcie().set_bit()) }
+                }
+
+                /// Stop listening for the transmission complete interrupt
+                pub fn unlisten(&mut self) {
+                    // NOTE(unsafe) see `listen`
+                    unsafe { (*$USARTX::ptr()).cr1.modify(|_, w| w.tcie().clear_bit()) }
+                }
+
+                /// Releases the bus driver once the transmission is complete
+                ///
+                /// Call this from the USART interrupt handler after `listen`.
+                pub fn handle_tc(&mut self) {
+                    // NOTE(unsafe) atomic read with no side effects
+                    let usart = unsafe { &*$USARTX::ptr() };
+                    if usart.sr.read().tc().bit_is_set() {
+                        // Clear TC so the interrupt does not fire again
+                        // until the next transmission
+                        usart.sr.modify(|_, w| w.tc().clear_bit());
+                        self.release_de();
+                    }
+                }
+
+                pub fn release(mut self) -> (Tx<$USARTX>, DE, DELAY) {
+                    self.de.set_low().ok();
+                    (self.tx, self.de, self.delay)
+                }
+            }
+
+            impl<DE, DELAY> hal::serial::Write<u8> for Rs485<$USARTX, DE, DELAY>
+            where
+                DE: OutputPin,
+                DELAY: DelayUs<u32>,
+            {
+                type Error = Error;
+
+                fn flush(&mut self) -> nb::Result<(), Self::Error> {
+                    if self.active {
+                        self.tx.flush()?;
+                        self.release_de();
+                    }
+                    Ok(())
+                }
+
+                fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+                    self.assert_de();
+                    self.tx.write(byte)
+                }
+            }
+
+            impl Tx<$USARTX> {
+                /// Sends a break after the current transmission
+                ///
+                /// In LIN mode the break is 13 bits long.
+                pub fn send_break(&mut self) {
+                    // NOTE(unsafe) CR1 is not modified by the receiver half after
+                    // `split`, so this read-modify-write cannot race
+                    unsafe { (*$USARTX::ptr()).cr1.modify(|_, w| w.sbk().set_bit()) }
+                }
+
+                /// Sends the whole `buffer` using DMA
+                pub fn write_all_dma<CHANNEL, BUFFER>(
+                    self,
+                    channel: CHANNEL,
+                    buffer: BUFFER,
+                ) -> Result<
+                    TxTransfer<$USARTX, CHANNEL, BUFFER>,
+                    (dma::Error, Tx<$USARTX>, CHANNEL, BUFFER),
+                >
+                where
+                    CHANNEL: dma::TxChannel<$USARTX>,
+                    BUFFER: dma::ReadBuffer<Word = u8>,
+                {
+                    // NOTE(unsafe) only the address of the data register is used
+                    let usart = unsafe { &*$USARTX::ptr() };
+                    let dr = &usart.dr as *const _ as u32;
+                    // NOTE(unsafe) `channel` serves the transmit requests of this USART
+                    let transfer = match unsafe {
+                        dma::Transfer::memory_to_peripheral(channel, buffer, dr)
+                    } {
+                        Ok(transfer) => transfer,
+                        Err((error, channel, buffer)) => return Err((error, self, channel, buffer)),
+                    };
+                    // CR3 is shared with the receiver, set DMAT atomically
+                    bb::set(&usart.cr3, 7);
+
+                    Ok(TxTransfer { tx: self, transfer })
+                }
+            }
+
+            impl<CHANNEL, BUFFER> TxTransfer<$USARTX, CHANNEL, BUFFER>
+            where
+                CHANNEL: dma::Channel,
+            {
+                /// Returns `true` if all bytes have been handed to the USART
+                pub fn is_done(&self) -> bool {
+                    self.transfer.is_done()
+                }
+
+                /// Blocks until all bytes have been handed to the USART
+                ///
+                /// The last byte may still be shifted out, use `flush` on the
+                /// returned transmitter to wait for the line to become idle.
+                pub fn wait(
+                    self,
+                ) -> Result<(Tx<$USARTX>, CHANNEL, BUFFER), (dma::Error, Tx<$USARTX>, CHANNEL, BUFFER)>
+                {
+                    let res = self.transfer.wait();
+                    // NOTE(unsafe) CR3 is shared with the receiver, clear DMAT atomically
+                    bb::clear(unsafe { &(*$USARTX::ptr()).cr3 }, 7);
+                    match res {
+                        Ok((channel, buffer)) => Ok((self.tx, channel, buffer)),
+                        Err((error, channel, buffer)) => Err((error, self.tx, channel, buffer)),
+                    }
+                }
+
+                /// Stops the transfer and releases the transmitter, the channel and the buffer
+                pub fn abort(self) -> (Tx<$USARTX>, CHANNEL, BUFFER) {
+                    let (channel, buffer) = self.transfer.abort();
+                    // NOTE(unsafe) CR3 is shared with the receiver, clear DMAT atomically
+                    bb::clear(unsafe { &(*$USARTX::ptr()).cr3 }, 7);
+                    (self.tx, channel, buffer)
+                }
+            }
+
+            impl Rx<$USARTX> {
+                /// Fills the whole `buffer` with received bytes using DMA
+                pub fn read_exact_dma<CHANNEL, BUFFER>(
+                    self,
+                    channel: CHANNEL,
+                    buffer: BUFFER,
+                ) -> Result<
+                    RxTransfer<$USARTX, CHANNEL, BUFFER>,
+                    (dma::Error, Rx<$USARTX>, CHANNEL, BUFFER),
+                >
+                where
+                    CHANNEL: dma::RxChannel<$USARTX>,
+                    BUFFER: dma::WriteBuffer<Word = u8>,
+                {
+                    // NOTE(unsafe) only the address of the data register is used
+                    let usart = unsafe { &*$USARTX::ptr() };
+                    let dr = &usart.dr as *const _ as u32;
+                    // NOTE(unsafe) `channel` serves the receive requests of this USART
+                    let transfer = match unsafe {
+                        dma::Transfer::peripheral_to_memory(channel, dr, buffer)
+                    } {
+                        Ok(transfer) => transfer,
+                        Err((error, channel, buffer)) => return Err((error, self, channel, buffer)),
+                    };
+                    // CR3 is shared with the transmitter, set DMAR atomically
+                    bb::set(&usart.cr3, 6);
+
+                    Ok(RxTransfer { rx: self, transfer })
+                }
+            }
+
+            impl Rx<$USARTX> {
+                /// Continuously receives into the ring `buffer` using circular DMA
+                ///
+                /// Listen for `Event::Idle` before splitting the serial to get an
+                /// interrupt at the end of every frame.
+                pub fn read_circular_dma<CHANNEL, const N: usize>(
+                    self,
+                    mut channel: CHANNEL,
+                    buffer: &'static mut [u8; N],
+                ) -> Result<
+                    RxRing<$USARTX, CHANNEL, N>,
+                    (dma::Error, Rx<$USARTX>, CHANNEL, &'static mut [u8; N]),
+                >
+                where
+                    CHANNEL: dma::RxChannel<$USARTX>,
+                {
+                    // NOTE(unsafe) only the address of the data register is used
+                    let usart = unsafe { &*$USARTX::ptr() };
+                    // NOTE(unsafe) `channel` serves the receive requests of this USART
+                    let setup = unsafe {
+                        dma::setup(
+                            &mut channel,
+                            dma::Direction::PeripheralToMemory,
+                            &usart.dr as *const _ as u32,
+                            buffer.as_ptr() as u32,
+                            N,
+                            dma::WordSize::Bits8,
+                            true,
+                        )
+                    };
+                    if let Err(error) = setup {
+                        return Err((error, self, channel, buffer));
+                    }
+                    atomic::compiler_fence(Ordering::Release);
+                    // NOTE(unsafe) the ring owns the buffer until the channel is stopped
+                    unsafe { channel.start() };
+                    // CR3 is shared with the transmitter, set DMAR atomically
+                    bb::set(&usart.cr3, 6);
+
+                    Ok(RxRing {
+                        rx: self,
+                        channel,
+                        buffer,
+                        read: 0,
+                        write: 0,
+                    })
+                }
+            }
+
+            impl<CHANNEL, const N: usize> RxRing<$USARTX, CHANNEL, N>
+            where
+                CHANNEL: dma::Channel,
+            {
+                /// Hands the frame received before the line went idle to `f`
+                ///
+                /// The bytes are passed as two slices, the second one is non-empty if
+                /// the frame wraps around the end of the ring buffer. Returns
+                /// `WouldBlock` while the line has not been idle since the last call.
+                pub fn read_frame<R, F>(&mut self, f: F) -> nb::Result<R, Error>
+                where
+                    F: FnOnce(&[u8], &[u8]) -> R,
+                {
+                    // NOTE(unsafe) the receiver owns the IDLE flag
+                    let usart = unsafe { &*$USARTX::ptr() };
+                    if usart.sr.read().idle().bit_is_clear() {
+                        return Err(nb::Error::WouldBlock);
+                    }
+                    // IDLE is cleared by the SR read above followed by a DR read
+                    let _ = usart.dr.read();
+
+                    self.drain(f).map_err(nb::Error::Other)
+                }
+
+                /// Stops receiving and releases the receiver, the channel and the buffer
+                pub fn stop(mut self) -> (Rx<$USARTX>, CHANNEL, &'static mut [u8; N]) {
+                    self.channel.stop();
+                    // NOTE(unsafe) CR3 is shared with the transmitter, clear DMAR atomically
+                    bb::clear(unsafe { &(*$USARTX::ptr()).cr3 }, 6);
+                    atomic::compiler_fence(Ordering::Acquire);
+                    (self.rx, self.channel, self.buffer)
+                }
+            }
+
+            impl<CHANNEL, BUFFER> RxTransfer<$USARTX, CHANNEL, BUFFER>
+            where
+                CHANNEL: dma::Channel,
+            {
+                /// Returns `true` if the buffer has been filled
+                pub fn is_done(&self) -> bool {
+                    self.transfer.is_done()
+                }
+
+                /// Blocks until the buffer has been filled
+                pub fn wait(
+                    self,
+                ) -> Result<(Rx<$USARTX>, CHANNEL, BUFFER), (dma::Error, Rx<$USARTX>, CHANNEL, BUFFER)>
+                {
+                    let res = self.transfer.wait();
+                    // NOTE(unsafe) CR3 is shared with the transmitter, clear DMAR atomically
+                    bb::clear(unsafe { &(*$USARTX::ptr()).cr3 }, 6);
+                    match res {
+                        Ok((channel, buffer)) => Ok((self.rx, channel, buffer)),
+                        Err((error, channel, buffer)) => Err((error, self.rx, channel, buffer)),
+                    }
+                }
+
+                /// Stops the transfer and releases the receiver, the channel and the buffer
+                pub fn abort(self) -> (Rx<$USARTX>, CHANNEL, BUFFER) {
+                    let (channel, buffer) = self.transfer.abort();
+                    // NOTE(unsafe) CR3 is shared with the transmitter, clear DMAR atomically
+                    bb::clear(unsafe { &(*$USARTX::ptr()).cr3 }, 6);
+                    (self.rx, channel, buffer)
+                }
+            }
+
+            impl hal::serial::Read<u8> for Rx<$USARTX> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u8, Error> {
+                    self.read_u16().map(|word| word as u8)
+                }
+            }
+
+            impl Rx<$USARTX> {
+                /// Reads a 9-bit word when `WordLength::DataBits9` is selected
+                pub fn read_u16(&mut self) -> nb::Result<u16, Error> {
+                    if let Some(word) = self.pending.take() {
+                        return Ok(word);
+                    }
+
+                    // NOTE(unsafe) atomic read with no side effects
                     let sr = unsafe { (*$USARTX::ptr()).sr.read() };
 
-                    Err(if sr.pe().bit_is_set() {
-                        nb::Error::Other(Error::Parity)
+                    let error = if sr.pe().bit_is_set() {
+                        Some(Error::Parity)
                     } else if sr.fe().bit_is_set() {
-                        nb::Error::Other(Error::Framing)
+                        Some(Error::Framing)
                     } else if sr.nf().bit_is_set() {
-                        nb::Error::Other(Error::Noise)
+                        Some(Error::Noise)
                     } else if sr.ore().bit_is_set() {
-                        nb::Error::Other(Error::Overrun)
+                        Some(Error::Overrun)
+                    } else {
+                        None
+                    };
+
+                    if let Some(error) = error {
+                        // The error flags are cleared by reading SR followed
+                        // by DR
+                        // NOTE(unsafe) atomic read, clears the flags and RXNE
+                        let word = unsafe { (*$USARTX::ptr()).dr.read().dr().bits() };
+                        // A noisy word and the word received before an
+                        // overrun are valid, the next read returns them
+                        if matches!(error, Error::Noise | Error::Overrun) {
+                            self.pending = Some(word);
+                        }
+                        Err(nb::Error::Other(error))
                     } else if sr.rxne().bit_is_set() {
-                        // NOTE(read_volatile) see `write_volatile` below
-                        return Ok(unsafe { ptr::read_volatile(&(*$USARTX::ptr()).dr as *const _ as *const _) });
+                        // NOTE(unsafe) atomic read, clears RXNE
+                        Ok(unsafe { (*$USARTX::ptr()).dr.read().dr().bits() })
                     } else {
-                        nb::Error::WouldBlock
-                    })
+                        Err(nb::Error::WouldBlock)
+                    }
                 }
             }
 
@@ -369,14 +1945,52 @@ macro_rules! usart {
                     }
                 }
             }
+
+            impl Tx<$USARTX> {
+                /// Writes a 9-bit word when `WordLength::DataBits9` is selected
+                pub fn write_u16(&mut self, word: u16) -> nb::Result<(), Error> {
+                    // NOTE(unsafe) atomic read with no side effects
+                    let sr = unsafe { (*$USARTX::ptr()).sr.read() };
+
+                    if sr.txe().bit_is_set() {
+                        // NOTE(unsafe) atomic write to stateless register
+                        unsafe { (*$USARTX::ptr()).dr.write(|w| w.dr().bits(word)) }
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+            }
         )+
     }
 }
 
 usart! {
-    USART1: (usart1, apb2enr, usart1en, apb2_clk, Serial1Ext),
-    USART2: (usart2, apb1enr, usart2en, apb1_clk, Serial2Ext),
-    USART3: (usart3, apb1enr, usart3en, apb1_clk, Serial3Ext),
+    USART1: (usart1, apb2enr, usart1en, apb2_clk, Serial1Ext, true),
+    USART2: (usart2, apb1enr, usart2en, apb1_clk, Serial2Ext, true),
+    USART3: (usart3, apb1enr, usart3en, apb1_clk, Serial3Ext, true),
+}
+
+#[cfg(any(feature = "stm32l151", feature = "stm32l152", feature = "stm32l162"))]
+usart! {
+    UART4: (uart4, apb1enr, usart4en, apb1_clk, Serial4Ext, false),
+    UART5: (uart5, apb1enr, usart5en, apb1_clk, Serial5Ext, false),
+}
+
+impl<USART, const TX: usize, const RX: usize> fmt::Write for BufferedSerial<USART, TX, RX>
+where
+    BufferedSerial<USART, TX, RX>: hal::serial::Write<u8>,
+{
+    /// Queues `s` for transmission without blocking
+    ///
+    /// Fails if the transmit queue cannot hold the whole string, the part
+    /// that fits is still sent.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.as_bytes() {
+            self.write(*c).map_err(|_| fmt::Error)?;
+        }
+        Ok(())
+    }
 }
 
 impl<USART> fmt::Write for Serial<USART>